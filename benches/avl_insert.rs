@@ -16,8 +16,8 @@
 extern crate criterion;
 extern crate examples;
 
-use examples::avl;
 use criterion::Criterion;
+use examples::avl;
 
 fn inserts(n: u32) {
     use avl::tree::Tree;
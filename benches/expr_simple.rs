@@ -22,7 +22,7 @@ use std::collections::HashMap;
 
 fn bench_simple(c: &mut Criterion) {
     c.bench_function("expr_simple", |b| {
-        b.iter(|| eval("3+3*5/(3*3)", &HashMap::new()))
+        b.iter(|| eval("3+3*5/(3*3)", &HashMap::<String, f64>::new()))
     });
 }
 
@@ -0,0 +1,78 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+#[macro_use]
+extern crate criterion;
+extern crate examples;
+
+use criterion::Criterion;
+use examples::avl::Tree;
+
+const SIZE: u32 = 10000;
+
+/// Keys in a scrambled but reproducible order, see ordered_map.rs.
+fn scrambled(n: u32) -> impl Iterator<Item = u32> {
+    (0..n).map(move |i| (i * 7919) % n)
+}
+
+fn bench_lookups(c: &mut Criterion) {
+    let mut tree = Tree::new();
+    for key in scrambled(SIZE) {
+        tree.insert(key, key);
+    }
+    let frozen = tree.freeze();
+    let sorted: Vec<u32> = (0..SIZE).collect();
+
+    c.bench_function(&format!("tree find {}", SIZE), |b| {
+        b.iter(|| {
+            for key in scrambled(SIZE) {
+                criterion::black_box(tree.find(key));
+            }
+        })
+    });
+    c.bench_function(&format!("frozen get {}", SIZE), |b| {
+        b.iter(|| {
+            for key in scrambled(SIZE) {
+                criterion::black_box(frozen.get(&key));
+            }
+        })
+    });
+    c.bench_function(&format!("tree find sorted {}", SIZE), |b| {
+        b.iter(|| {
+            for &key in &sorted {
+                criterion::black_box(tree.find(key));
+            }
+        })
+    });
+    c.bench_function(&format!("frozen get_many sorted {}", SIZE), |b| {
+        b.iter(|| criterion::black_box(frozen.get_many(&sorted)))
+    });
+    c.bench_function(&format!("tree range_scans {}", SIZE), |b| {
+        b.iter(|| {
+            for start in (0..SIZE).step_by(10) {
+                criterion::black_box(tree.range(start..start + 100).count());
+            }
+        })
+    });
+    c.bench_function(&format!("frozen range_scans {}", SIZE), |b| {
+        b.iter(|| {
+            for start in (0..SIZE).step_by(10) {
+                criterion::black_box(frozen.range(start..start + 100).count());
+            }
+        })
+    });
+}
+
+criterion_group!(benches, bench_lookups);
+criterion_main!(benches);
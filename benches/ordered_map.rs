@@ -0,0 +1,126 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+#[macro_use]
+extern crate criterion;
+extern crate examples;
+
+use criterion::Criterion;
+use examples::avl;
+use examples::map::{OrderedMap, RbTree, Treap};
+use std::collections::BTreeMap;
+
+const SIZE: u32 = 1000;
+
+/// Keys in a scrambled but reproducible order. Since 7919 is a prime
+/// and does not divide the size, every key in the range is produced
+/// exactly once.
+fn scrambled(n: u32) -> impl Iterator<Item = u32> {
+    (0..n).map(move |i| (i * 7919) % n)
+}
+
+fn build<M>(keys: impl Iterator<Item = u32>) -> M
+where
+    M: OrderedMap<u32, u32> + Default,
+{
+    let mut map = M::default();
+    for key in keys {
+        map.insert(key, key);
+    }
+    map
+}
+
+fn sequential_inserts<M>(n: u32)
+where
+    M: OrderedMap<u32, u32> + Default,
+{
+    build::<M>(0..n);
+}
+
+fn random_inserts<M>(n: u32)
+where
+    M: OrderedMap<u32, u32> + Default,
+{
+    build::<M>(scrambled(n));
+}
+
+fn lookups<M>(map: &M, n: u32)
+where
+    M: OrderedMap<u32, u32>,
+{
+    for key in scrambled(n) {
+        criterion::black_box(map.get(&key));
+    }
+}
+
+fn removes<M>(n: u32)
+where
+    M: OrderedMap<u32, u32> + Default,
+{
+    let mut map = build::<M>(scrambled(n));
+    for key in 0..n {
+        map.remove(&key);
+    }
+}
+
+fn range_scans<M>(map: &M, n: u32)
+where
+    M: OrderedMap<u32, u32>,
+{
+    for start in (0..n).step_by(10) {
+        criterion::black_box(map.range(start..start + 100).count());
+    }
+}
+
+/// Run all workloads on one map implementation.
+fn bench_map<M>(c: &mut Criterion, name: &str)
+where
+    M: OrderedMap<u32, u32> + Default,
+{
+    c.bench_function(&format!("{} sequential_inserts {}", name, SIZE), |b| {
+        b.iter(|| sequential_inserts::<M>(SIZE))
+    });
+    c.bench_function(&format!("{} random_inserts {}", name, SIZE), |b| {
+        b.iter(|| random_inserts::<M>(SIZE))
+    });
+    let map = build::<M>(scrambled(SIZE));
+    c.bench_function(&format!("{} lookups {}", name, SIZE), |b| {
+        b.iter(|| lookups(&map, SIZE))
+    });
+    c.bench_function(&format!("{} range_scans {}", name, SIZE), |b| {
+        b.iter(|| range_scans(&map, SIZE))
+    });
+    c.bench_function(&format!("{} removes {}", name, SIZE), |b| {
+        b.iter(|| removes::<M>(SIZE))
+    });
+}
+
+fn bench_avl(c: &mut Criterion) {
+    bench_map::<avl::Tree<u32, u32>>(c, "avl");
+}
+
+fn bench_rbtree(c: &mut Criterion) {
+    bench_map::<RbTree<u32, u32>>(c, "rbtree");
+}
+
+fn bench_treap(c: &mut Criterion) {
+    bench_map::<Treap<u32, u32>>(c, "treap");
+}
+
+fn bench_btree(c: &mut Criterion) {
+    bench_map::<BTreeMap<u32, u32>>(c, "btree");
+}
+
+criterion_group!(benches, bench_avl, bench_rbtree, bench_treap, bench_btree);
+criterion_main!(benches);
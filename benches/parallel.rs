@@ -0,0 +1,48 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+#[macro_use]
+extern crate criterion;
+extern crate examples;
+
+use criterion::Criterion;
+use examples::avl::Tree;
+
+const SIZE: u64 = 200_000;
+const THREADS: usize = 4;
+
+/// Entries in a scrambled but reproducible order, see ordered_map.rs.
+fn scrambled(n: u64) -> Vec<(u64, u64)> {
+    (0..n).map(|i| ((i * 7919) % n, i)).collect()
+}
+
+fn bench_bulk(c: &mut Criterion) {
+    c.bench_function(&format!("collect {}", SIZE), |b| {
+        b.iter(|| criterion::black_box(scrambled(SIZE).into_iter().collect::<Tree<_, _>>()))
+    });
+    c.bench_function(&format!("par_build {} threads {}", SIZE, THREADS), |b| {
+        b.iter(|| criterion::black_box(Tree::<_, _>::par_build(scrambled(SIZE), THREADS)))
+    });
+
+    let tree: Tree<u64, u64> = scrambled(SIZE).into_iter().collect();
+    let keys: Vec<u64> = scrambled(SIZE).into_iter().map(|(key, _)| key).collect();
+    c.bench_function(&format!("get_many {}", SIZE), |b| {
+        b.iter(|| criterion::black_box(tree.get_many(&keys)))
+    });
+    c.bench_function(&format!("par_get_many {} threads {}", SIZE, THREADS), |b| {
+        b.iter(|| criterion::black_box(tree.par_get_many(&keys, THREADS)))
+    });
+}
+
+criterion_group!(benches, bench_bulk);
+criterion_main!(benches);
@@ -12,19 +12,19 @@
 // implied.  See the License for the specific language governing
 // permissions and limitations under the License.
 
-extern crate data_structures;
+extern crate examples;
 
-use data_structures::avl::Tree;
+use examples::avl::Tree;
 
 fn main() {
     let mut tree = Tree::new();
     for i in 1..11 {
         tree.insert(i, i * i);
         println!("After inserting {}", i);
-        tree.pretty();
+        print!("{}", tree.pretty());
         tree.insert(2 * 11 - i, i * i * i);
         println!("After inserting {}", 2 * 11 - i);
-        tree.pretty();
+        print!("{}", tree.pretty());
     }
 
     for i in 1..11 {
@@ -28,7 +28,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (mut writer, reader) = UdpFramed::new(socket, BytesCodec::new()).split();
     let forwarder = reader.for_each(move |(bytes, _from)| {
         for peer in peers.iter() {
-            writer.start_send((bytes.clone().into(), peer.clone()))?;
+            writer.start_send((bytes.clone().into(), *peer))?;
         }
         writer.poll_complete()?;
         Ok(())
@@ -0,0 +1,50 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Generate the C header and build the C test program.
+//!
+//! The header is written to `OUT_DIR/include/examples.h`, and
+//! `tests/c_api.rs` checks that the copy in `include` matches it. The
+//! test program is built against the generated header as a static
+//! library with the entry point `c_api_test`, which `tests/c_api.rs`
+//! links and calls. The functions it uses come from this crate when
+//! the test is linked.
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let include = out.join("include");
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_crate(&dir)
+        .with_config(config)
+        .generate()
+        .expect("cannot generate C header")
+        .write_to_file(include.join("examples.h"));
+
+    cc::Build::new()
+        .file(dir.join("tests").join("c").join("c_api_test.c"))
+        .include(&include)
+        .warnings(true)
+        .cargo_metadata(false)
+        .compile("c_api_test");
+    println!("cargo:rustc-link-search=native={}", out.display());
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=tests/c/c_api_test.c");
+}
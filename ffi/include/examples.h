@@ -0,0 +1,210 @@
+/* Copyright 2019 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"); you
+ * may not use this file except in compliance with the License.  You
+ * may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+ * implied.  See the License for the specific language governing
+ * permissions and limitations under the License.
+ */
+
+#ifndef EXAMPLES_H
+#define EXAMPLES_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Iterator over the entries of an `AvlTree` in key order.
+ */
+typedef struct AvlIter AvlIter;
+
+/**
+ * Tree with byte strings as keys and values.
+ */
+typedef struct AvlTree AvlTree;
+
+/**
+ * Parsed expression, or the error from parsing it.
+ */
+typedef struct Expr Expr;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create an empty tree, which is freed with `avl_tree_free`.
+ */
+struct AvlTree *avl_tree_new(void);
+
+/**
+ * Free a tree and all entries in it. Freeing a null pointer does
+ * nothing.
+ *
+ * # Safety
+ *
+ * The tree must have been created by `avl_tree_new` and not freed.
+ */
+void avl_tree_free(struct AvlTree *tree);
+
+/**
+ * Number of entries in a tree.
+ *
+ * # Safety
+ *
+ * The tree must be a valid pointer from `avl_tree_new`.
+ */
+size_t avl_tree_len(const struct AvlTree *tree);
+
+/**
+ * Insert a copy of a key and a value, returning true if the key was
+ * present, in which case its old value is replaced.
+ *
+ * # Safety
+ *
+ * The tree must be a valid pointer from `avl_tree_new`, and the key
+ * and value must point to the given number of bytes.
+ */
+bool avl_tree_insert(struct AvlTree *tree,
+                     const uint8_t *key,
+                     size_t key_len,
+                     const uint8_t *value,
+                     size_t value_len);
+
+/**
+ * Look up a key, returning false if it is not present.
+ *
+ * If the key is present, the value is stored in `value` and
+ * `value_len`. It points into the tree and stays valid until the
+ * tree is changed or freed.
+ *
+ * # Safety
+ *
+ * The tree must be a valid pointer from `avl_tree_new`, and the key
+ * must point to the given number of bytes.
+ */
+bool avl_tree_get(const struct AvlTree *tree,
+                  const uint8_t *key,
+                  size_t key_len,
+                  const uint8_t **value,
+                  size_t *value_len);
+
+/**
+ * Remove a key, returning false if it was not present.
+ *
+ * # Safety
+ *
+ * The tree must be a valid pointer from `avl_tree_new`, and the key
+ * must point to the given number of bytes.
+ */
+bool avl_tree_remove(struct AvlTree *tree, const uint8_t *key, size_t key_len);
+
+/**
+ * Start iterating over the entries of a tree in key order. The
+ * iterator is freed with `avl_iter_free`.
+ *
+ * # Safety
+ *
+ * The tree must be a valid pointer from `avl_tree_new`. It must not
+ * be changed or freed until the iterator is freed.
+ */
+struct AvlIter *avl_tree_iter(const struct AvlTree *tree);
+
+/**
+ * Get the next entry of an iterator, returning false if there are no
+ * more entries.
+ *
+ * The key and the value point into the tree and stay valid until the
+ * tree is changed or freed. Any of the output parameters can be null.
+ *
+ * # Safety
+ *
+ * The iterator must be a valid pointer from `avl_tree_iter`.
+ */
+bool avl_iter_next(struct AvlIter *iter,
+                   const uint8_t **key,
+                   size_t *key_len,
+                   const uint8_t **value,
+                   size_t *value_len);
+
+/**
+ * Free an iterator. Freeing a null pointer does nothing.
+ *
+ * # Safety
+ *
+ * The iterator must have been created by `avl_tree_iter` and not
+ * freed.
+ */
+void avl_iter_free(struct AvlIter *iter);
+
+/**
+ * Parse an expression, which is freed with `expr_free`.
+ *
+ * A handle is returned even if the expression is not valid, and
+ * `expr_error` gives the error message in that case.
+ *
+ * # Safety
+ *
+ * The text must be a valid pointer to a NUL-terminated string.
+ */
+struct Expr *expr_parse(const char *text);
+
+/**
+ * Evaluate an expression with the values of its variables, given as
+ * an array of names and an array of values of the same length.
+ *
+ * Returns false if the expression could not be parsed or a variable
+ * has no value, and `expr_error` then gives the error message.
+ * Otherwise the value is stored in `result`.
+ *
+ * # Safety
+ *
+ * The expression must be a valid pointer from `expr_parse`. The
+ * names must be `count` pointers to NUL-terminated strings, the
+ * values must be `count` numbers and the result must be valid.
+ */
+bool expr_eval(struct Expr *expr,
+               const char *const *names,
+               const double *values,
+               size_t count,
+               double *result);
+
+/**
+ * Message of the error from parsing an expression or from its last
+ * evaluation, or null if there was no error.
+ *
+ * The message stays valid until the expression is evaluated again
+ * or freed.
+ *
+ * # Safety
+ *
+ * The expression must be a valid pointer from `expr_parse`.
+ */
+const char *expr_error(const struct Expr *expr);
+
+/**
+ * Free an expression. Freeing a null pointer does nothing.
+ *
+ * # Safety
+ *
+ * The expression must have been created by `expr_parse` and not
+ * freed.
+ */
+void expr_free(struct Expr *expr);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* EXAMPLES_H */
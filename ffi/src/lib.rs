@@ -0,0 +1,346 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! C interface to the AVL tree and the expression evaluator.
+//!
+//! Objects are given to C as pointers to opaque structures, which are
+//! created and freed by functions in this module. Keys and values of
+//! the tree are byte strings given as a pointer and a length, and the
+//! tree keeps its own copies of them. The build script generates the C
+//! header from this file, and the tests check that the checked-in copy
+//! in `include/examples.h` matches it.
+
+use examples::avl::tree::{Iter, Tree};
+use examples::expr::tree::ExprTree;
+use examples::expr::{self, Environment};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::{ptr, slice};
+
+/// Tree with byte strings as keys and values.
+pub struct AvlTree {
+    tree: Tree<Vec<u8>, Vec<u8>>,
+}
+
+/// Iterator over the entries of an `AvlTree` in key order.
+pub struct AvlIter {
+    // The iterator borrows the tree, which the caller has to keep
+    // alive and unchanged until the iterator is freed.
+    iter: Iter<'static, Vec<u8>, Vec<u8>>,
+}
+
+/// Parsed expression, or the error from parsing it.
+pub struct Expr {
+    tree: Option<ExprTree>,
+    /// Message of the last error, if any.
+    error: Option<CString>,
+}
+
+/// Borrow a byte string given by a pointer and a length, where the
+/// pointer may be null if the length is zero.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len)
+    }
+}
+
+/// Store a byte string in a pair of output parameters, which may be
+/// null if the caller does not need them.
+unsafe fn output(bytes: &[u8], data: *mut *const u8, len: *mut usize) {
+    if !data.is_null() {
+        *data = bytes.as_ptr();
+    }
+    if !len.is_null() {
+        *len = bytes.len();
+    }
+}
+
+/// Create an empty tree, which is freed with `avl_tree_free`.
+#[no_mangle]
+pub extern "C" fn avl_tree_new() -> *mut AvlTree {
+    Box::into_raw(Box::new(AvlTree { tree: Tree::new() }))
+}
+
+/// Free a tree and all entries in it. Freeing a null pointer does
+/// nothing.
+///
+/// # Safety
+///
+/// The tree must have been created by `avl_tree_new` and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_free(tree: *mut AvlTree) {
+    if !tree.is_null() {
+        drop(Box::from_raw(tree));
+    }
+}
+
+/// Number of entries in a tree.
+///
+/// # Safety
+///
+/// The tree must be a valid pointer from `avl_tree_new`.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_len(tree: *const AvlTree) -> usize {
+    (*tree).tree.len()
+}
+
+/// Insert a copy of a key and a value, returning true if the key was
+/// present, in which case its old value is replaced.
+///
+/// # Safety
+///
+/// The tree must be a valid pointer from `avl_tree_new`, and the key
+/// and value must point to the given number of bytes.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_insert(
+    tree: *mut AvlTree,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> bool {
+    let key = bytes(key, key_len).to_vec();
+    let value = bytes(value, value_len).to_vec();
+    (*tree).tree.insert(key, value).is_some()
+}
+
+/// Look up a key, returning false if it is not present.
+///
+/// If the key is present, the value is stored in `value` and
+/// `value_len`. It points into the tree and stays valid until the
+/// tree is changed or freed.
+///
+/// # Safety
+///
+/// The tree must be a valid pointer from `avl_tree_new`, and the key
+/// must point to the given number of bytes.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_get(
+    tree: *const AvlTree,
+    key: *const u8,
+    key_len: usize,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> bool {
+    match (*tree).tree.get(&bytes(key, key_len).to_vec()) {
+        None => false,
+        Some(found) => {
+            output(found, value, value_len);
+            true
+        }
+    }
+}
+
+/// Remove a key, returning false if it was not present.
+///
+/// # Safety
+///
+/// The tree must be a valid pointer from `avl_tree_new`, and the key
+/// must point to the given number of bytes.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_remove(
+    tree: *mut AvlTree,
+    key: *const u8,
+    key_len: usize,
+) -> bool {
+    (*tree).tree.remove(&bytes(key, key_len).to_vec()).is_some()
+}
+
+/// Start iterating over the entries of a tree in key order. The
+/// iterator is freed with `avl_iter_free`.
+///
+/// # Safety
+///
+/// The tree must be a valid pointer from `avl_tree_new`. It must not
+/// be changed or freed until the iterator is freed.
+#[no_mangle]
+pub unsafe extern "C" fn avl_tree_iter(tree: *const AvlTree) -> *mut AvlIter {
+    let tree: &'static AvlTree = &*tree;
+    Box::into_raw(Box::new(AvlIter {
+        iter: tree.tree.iter(),
+    }))
+}
+
+/// Get the next entry of an iterator, returning false if there are no
+/// more entries.
+///
+/// The key and the value point into the tree and stay valid until the
+/// tree is changed or freed. Any of the output parameters can be null.
+///
+/// # Safety
+///
+/// The iterator must be a valid pointer from `avl_tree_iter`.
+#[no_mangle]
+pub unsafe extern "C" fn avl_iter_next(
+    iter: *mut AvlIter,
+    key: *mut *const u8,
+    key_len: *mut usize,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> bool {
+    match (*iter).iter.next() {
+        None => false,
+        Some((found_key, found_value)) => {
+            output(found_key, key, key_len);
+            output(found_value, value, value_len);
+            true
+        }
+    }
+}
+
+/// Free an iterator. Freeing a null pointer does nothing.
+///
+/// # Safety
+///
+/// The iterator must have been created by `avl_tree_iter` and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn avl_iter_free(iter: *mut AvlIter) {
+    if !iter.is_null() {
+        drop(Box::from_raw(iter));
+    }
+}
+
+fn message<E: ToString>(error: E) -> Option<CString> {
+    // Messages do not contain NUL characters unless they quote the
+    // input, which cannot contain any since it was a C string.
+    CString::new(error.to_string()).ok()
+}
+
+/// Parse an expression, which is freed with `expr_free`.
+///
+/// A handle is returned even if the expression is not valid, and
+/// `expr_error` gives the error message in that case.
+///
+/// # Safety
+///
+/// The text must be a valid pointer to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn expr_parse(text: *const c_char) -> *mut Expr {
+    let expr = match CStr::from_ptr(text).to_str() {
+        Err(error) => Expr {
+            tree: None,
+            error: message(error),
+        },
+        Ok(text) => match expr::parse(text) {
+            Ok(tree) => Expr {
+                tree: Some(tree),
+                error: None,
+            },
+            Err(error) => Expr {
+                tree: None,
+                error: message(error),
+            },
+        },
+    };
+    Box::into_raw(Box::new(expr))
+}
+
+/// Variables given to `expr_eval` as two arrays.
+struct Variables<'a> {
+    names: &'a [*const c_char],
+    values: &'a [f64],
+}
+
+impl<'a> Environment for Variables<'a> {
+    fn value(&self, name: &str) -> Option<f64> {
+        // Safety: the names are checked by the caller of `expr_eval`.
+        let position = self
+            .names
+            .iter()
+            .position(|&other| unsafe { CStr::from_ptr(other) }.to_bytes() == name.as_bytes())?;
+        Some(self.values[position])
+    }
+}
+
+/// Evaluate an expression with the values of its variables, given as
+/// an array of names and an array of values of the same length.
+///
+/// Returns false if the expression could not be parsed or a variable
+/// has no value, and `expr_error` then gives the error message.
+/// Otherwise the value is stored in `result`.
+///
+/// # Safety
+///
+/// The expression must be a valid pointer from `expr_parse`. The
+/// names must be `count` pointers to NUL-terminated strings, the
+/// values must be `count` numbers and the result must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn expr_eval(
+    expr: *mut Expr,
+    names: *const *const c_char,
+    values: *const f64,
+    count: usize,
+    result: *mut f64,
+) -> bool {
+    let expr = &mut *expr;
+    let tree = match expr.tree {
+        None => return false,
+        Some(ref tree) => tree,
+    };
+    let variables = if count == 0 {
+        Variables {
+            names: &[],
+            values: &[],
+        }
+    } else {
+        Variables {
+            names: slice::from_raw_parts(names, count),
+            values: slice::from_raw_parts(values, count),
+        }
+    };
+    match tree.eval(&variables) {
+        Ok(value) => {
+            expr.error = None;
+            *result = value;
+            true
+        }
+        Err(error) => {
+            expr.error = message(error);
+            false
+        }
+    }
+}
+
+/// Message of the error from parsing an expression or from its last
+/// evaluation, or null if there was no error.
+///
+/// The message stays valid until the expression is evaluated again
+/// or freed.
+///
+/// # Safety
+///
+/// The expression must be a valid pointer from `expr_parse`.
+#[no_mangle]
+pub unsafe extern "C" fn expr_error(expr: *const Expr) -> *const c_char {
+    match (*expr).error {
+        None => ptr::null(),
+        Some(ref error) => error.as_ptr(),
+    }
+}
+
+/// Free an expression. Freeing a null pointer does nothing.
+///
+/// # Safety
+///
+/// The expression must have been created by `expr_parse` and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn expr_free(expr: *mut Expr) {
+    if !expr.is_null() {
+        drop(Box::from_raw(expr));
+    }
+}
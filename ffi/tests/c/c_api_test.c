@@ -0,0 +1,97 @@
+/* Copyright 2019 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License"); you
+ * may not use this file except in compliance with the License.  You
+ * may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+ * implied.  See the License for the specific language governing
+ * permissions and limitations under the License.
+ */
+
+/* Test of the C interface, called from tests/c_api.rs. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "examples.h"
+
+#define CHECK(cond)                                                     \
+  do {                                                                  \
+    if (!(cond)) {                                                      \
+      fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__,  \
+              #cond);                                                   \
+      return 1;                                                         \
+    }                                                                   \
+  } while (0)
+
+static int insert(AvlTree *tree, const char *key, const char *value) {
+  return avl_tree_insert(tree, (const uint8_t *)key, strlen(key),
+                         (const uint8_t *)value, strlen(value));
+}
+
+static int test_tree(void) {
+  AvlTree *tree = avl_tree_new();
+  const uint8_t *value;
+  size_t value_len;
+
+  CHECK(!insert(tree, "bob", "2"));
+  CHECK(!insert(tree, "alice", "1"));
+  CHECK(!insert(tree, "carol", "3"));
+  CHECK(insert(tree, "bob", "two"));
+  CHECK(avl_tree_len(tree) == 3);
+
+  CHECK(avl_tree_get(tree, (const uint8_t *)"bob", 3, &value, &value_len));
+  CHECK(value_len == 3 && memcmp(value, "two", 3) == 0);
+  CHECK(!avl_tree_get(tree, (const uint8_t *)"dave", 4, &value, &value_len));
+
+  CHECK(avl_tree_remove(tree, (const uint8_t *)"carol", 5));
+  CHECK(!avl_tree_remove(tree, (const uint8_t *)"carol", 5));
+
+  {
+    const char *expected[] = {"alice", "bob"};
+    AvlIter *iter = avl_tree_iter(tree);
+    const uint8_t *key;
+    size_t key_len, count = 0;
+    while (avl_iter_next(iter, &key, &key_len, NULL, NULL)) {
+      CHECK(count < 2);
+      CHECK(key_len == strlen(expected[count]));
+      CHECK(memcmp(key, expected[count], key_len) == 0);
+      ++count;
+    }
+    CHECK(count == 2);
+    avl_iter_free(iter);
+  }
+
+  avl_tree_free(tree);
+  return 0;
+}
+
+static int test_expr(void) {
+  const char *names[] = {"x", "y"};
+  const double values[] = {12.0, 0.5};
+  double result;
+  Expr *expr;
+
+  expr = expr_parse("(10 - x) * 3 + y");
+  CHECK(expr_error(expr) == NULL);
+  CHECK(expr_eval(expr, names, values, 2, &result));
+  CHECK(result == -5.5);
+  CHECK(!expr_eval(expr, names, values, 1, &result));
+  CHECK(strcmp(expr_error(expr), "no value for variable 'y'") == 0);
+  expr_free(expr);
+
+  expr = expr_parse("10 +");
+  CHECK(expr_error(expr) != NULL);
+  CHECK(!expr_eval(expr, NULL, NULL, 0, &result));
+  expr_free(expr);
+  return 0;
+}
+
+int c_api_test(void) {
+  return test_tree() || test_expr();
+}
@@ -0,0 +1,63 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Check the C header and run the C test program.
+//!
+//! The build script generates the header and builds the C test program
+//! against it. The header in `include/examples.h` is checked in so that
+//! C users do not need to build the crate to get it. If the interface
+//! changes, update it with:
+//!
+//! ```text
+//! UPDATE_HEADER=1 cargo test -p examples-ffi
+//! ```
+
+// The C test program calls the functions of this crate, so link it even
+// though nothing in Rust uses it.
+extern crate examples_ffi;
+
+use std::env;
+use std::fs;
+use std::os::raw::c_int;
+use std::path::Path;
+
+#[link(name = "c_api_test", kind = "static")]
+extern "C" {
+    fn c_api_test() -> c_int;
+}
+
+#[test]
+fn header_is_current() {
+    let generated = Path::new(env!("OUT_DIR"))
+        .join("include")
+        .join("examples.h");
+    let generated = fs::read(generated).unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("include")
+        .join("examples.h");
+    if env::var_os("UPDATE_HEADER").is_some() {
+        fs::write(&path, &generated).unwrap();
+    }
+    let current = fs::read(&path).unwrap();
+    assert!(
+        current == generated,
+        "{} is out of date, run with UPDATE_HEADER=1 to update it",
+        path.display()
+    );
+}
+
+#[test]
+fn c_program() {
+    assert_eq!(unsafe { c_api_test() }, 0);
+}
@@ -0,0 +1,196 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Output formats of the tool.
+//!
+//! Keys and values are byte strings. The plain format writes them as
+//! text, replacing bytes that are not UTF-8. The TSV format escapes
+//! tab, newline, carriage return and backslash with a backslash so
+//! that each entry is one line with two fields. The JSON format writes
+//! them as JSON strings, also replacing bytes that are not UTF-8.
+
+use examples::avl::TreeStats;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Plain,
+    Tsv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "plain" => Ok(Format::Plain),
+            "tsv" => Ok(Format::Tsv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format '{}'", name)),
+        }
+    }
+}
+
+/// Escape a byte string as a TSV field.
+pub fn tsv(bytes: &[u8]) -> String {
+    let mut field = String::with_capacity(bytes.len());
+    for ch in String::from_utf8_lossy(bytes).chars() {
+        match ch {
+            '\t' => field.push_str("\\t"),
+            '\n' => field.push_str("\\n"),
+            '\r' => field.push_str("\\r"),
+            '\\' => field.push_str("\\\\"),
+            _ => field.push(ch),
+        }
+    }
+    field
+}
+
+/// Write a byte string as a JSON string.
+pub fn json(bytes: &[u8]) -> String {
+    let mut string = String::with_capacity(bytes.len() + 2);
+    string.push('"');
+    for ch in String::from_utf8_lossy(bytes).chars() {
+        match ch {
+            '"' => string.push_str("\\\""),
+            '\\' => string.push_str("\\\\"),
+            '\n' => string.push_str("\\n"),
+            '\r' => string.push_str("\\r"),
+            '\t' => string.push_str("\\t"),
+            ch if ch < ' ' => string.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => string.push(ch),
+        }
+    }
+    string.push('"');
+    string
+}
+
+impl Format {
+    /// Write the value of a key.
+    pub fn value<W: Write>(self, out: &mut W, value: &[u8]) -> io::Result<()> {
+        match self {
+            Format::Plain => writeln!(out, "{}", String::from_utf8_lossy(value)),
+            Format::Tsv => writeln!(out, "{}", tsv(value)),
+            Format::Json => writeln!(out, "{}", json(value)),
+        }
+    }
+
+    /// Write entries in key order. In JSON, they are written as an
+    /// array of objects with a key and a value, which keeps the order.
+    pub fn entries<'a, W, I>(self, out: &mut W, entries: I) -> io::Result<()>
+    where
+        W: Write,
+        I: Iterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+    {
+        if self == Format::Json {
+            write!(out, "[")?;
+        }
+        for (i, (key, value)) in entries.enumerate() {
+            match self {
+                Format::Plain => writeln!(
+                    out,
+                    "{}: {}",
+                    String::from_utf8_lossy(key),
+                    String::from_utf8_lossy(value)
+                )?,
+                Format::Tsv => writeln!(out, "{}\t{}", tsv(key), tsv(value))?,
+                Format::Json => {
+                    let separator = if i == 0 { "" } else { "," };
+                    write!(
+                        out,
+                        "{}\n  {{\"key\": {}, \"value\": {}}}",
+                        separator,
+                        json(key),
+                        json(value)
+                    )?
+                }
+            }
+        }
+        if self == Format::Json {
+            writeln!(out, "\n]")?;
+        }
+        Ok(())
+    }
+
+    /// Write the shape of the tree, with the number of nodes for each
+    /// balance factor.
+    pub fn stats<W: Write>(self, out: &mut W, stats: &TreeStats) -> io::Result<()> {
+        match self {
+            Format::Plain => {
+                writeln!(out, "height: {}", stats.height)?;
+                writeln!(out, "nodes: {}", stats.nodes)?;
+                for (balance, count) in &stats.balance {
+                    writeln!(out, "balance {}: {}", balance, count)?;
+                }
+            }
+            Format::Tsv => {
+                writeln!(out, "height\t{}", stats.height)?;
+                writeln!(out, "nodes\t{}", stats.nodes)?;
+                for (balance, count) in &stats.balance {
+                    writeln!(out, "balance {}\t{}", balance, count)?;
+                }
+            }
+            Format::Json => {
+                let balance: Vec<String> = stats
+                    .balance
+                    .iter()
+                    .map(|(balance, count)| format!("\"{}\": {}", balance, count))
+                    .collect();
+                writeln!(
+                    out,
+                    "{{\"height\": {}, \"nodes\": {}, \"balance\": {{{}}}}}",
+                    stats.height,
+                    stats.nodes,
+                    balance.join(", ")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(tsv(b"plain"), "plain");
+        assert_eq!(tsv(b"a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+        assert_eq!(json(b"say \"hi\"\n"), "\"say \\\"hi\\\"\\n\"");
+        assert_eq!(json(b"\x01\xff"), "\"\\u0001\u{fffd}\"");
+    }
+
+    #[test]
+    fn test_entries() {
+        let entries = [
+            (b"a".to_vec(), b"1".to_vec()),
+            (b"b".to_vec(), b"x\ty".to_vec()),
+        ];
+        let write = |format: Format| {
+            let mut out = Vec::new();
+            let iter = entries.iter().map(|(k, v)| (k, v));
+            format.entries(&mut out, iter).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(write(Format::Plain), "a: 1\nb: x\ty\n");
+        assert_eq!(write(Format::Tsv), "a\t1\nb\tx\\ty\n");
+        assert_eq!(
+            write(Format::Json),
+            "[\n  {\"key\": \"a\", \"value\": \"1\"},\n  {\"key\": \"b\", \"value\": \"x\\ty\"}\n]\n"
+        );
+    }
+}
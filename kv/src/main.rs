@@ -0,0 +1,344 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Command-line tool to read and change a tree stored in a file.
+//!
+//! ```text
+//! avl-kv [--format plain|tsv|json] FILE COMMAND [ARGS]
+//! ```
+//!
+//! The file holds the tree in the binary format of `avl::codec`, and a
+//! missing file is an empty tree. Commands that change the tree write
+//! it to a temporary file next to the data file, which is then renamed
+//! over it, so the file always holds either the old or the new tree.
+//!
+//! Each command locks `FILE.lock` while it reads, changes and writes
+//! the tree: shared for commands that only read it, and exclusive for
+//! commands that change it, so concurrent changes are not lost. The
+//! lock file is left in place, since removing it could let another
+//! process lock a file that is no longer there. Commands that only
+//! read the tree open an existing lock file read-only, and if there is
+//! no lock file and it cannot be created, for example in a read-only
+//! directory, they read the tree without a lock. No command can change
+//! the file there either, since the temporary file cannot be created.
+//!
+//! Keys and values are taken from the arguments as bytes, so they need
+//! not be valid UTF-8 on Unix.
+//!
+//! The exit status is 0 on success, 1 if `get` or `del` did not find
+//! the key, and 2 on errors.
+
+mod format;
+
+use crate::format::Format;
+use examples::avl::tree::Tree;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::result;
+
+type Data = Tree<Vec<u8>, Vec<u8>>;
+
+const USAGE: &str = "\
+usage: avl-kv [--format plain|tsv|json] FILE COMMAND [ARGS]
+
+commands:
+    get KEY              print the value of a key
+    put KEY VALUE        store a value under a key
+    del KEY              remove a key
+    range [START [END]]  print the entries from START up to END
+    dump                 print all entries
+    stats                print the height, node count and balance";
+
+enum Error {
+    Usage(String),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            Error::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+        }
+    }
+}
+
+type Result<T> = result::Result<T, Error>;
+
+enum Command {
+    Get(Vec<u8>),
+    Put(Vec<u8>, Vec<u8>),
+    Del(Vec<u8>),
+    Range(Bound<Vec<u8>>, Bound<Vec<u8>>),
+    Stats,
+}
+
+impl Command {
+    /// Check if the command can change the tree.
+    fn changes(&self) -> bool {
+        matches!(self, Command::Put(..) | Command::Del(..))
+    }
+}
+
+struct Args {
+    format: Format,
+    path: PathBuf,
+    command: Command,
+}
+
+fn usage<T>(message: &str) -> Result<T> {
+    Err(Error::Usage(message.to_string()))
+}
+
+/// Get an argument that has to be text, such as an option or command.
+fn text(arg: &OsStr) -> Result<&str> {
+    match arg.to_str() {
+        Some(text) => Ok(text),
+        None => usage(&format!("invalid argument '{}'", arg.to_string_lossy())),
+    }
+}
+
+/// Get the bytes of a key or value argument.
+#[cfg(unix)]
+fn bytes(arg: &OsString) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    arg.as_bytes().to_vec()
+}
+
+/// Get the bytes of a key or value argument, which is converted to
+/// UTF-8 where arguments are not bytes.
+#[cfg(not(unix))]
+fn bytes(arg: &OsString) -> Vec<u8> {
+    arg.to_string_lossy().as_bytes().to_vec()
+}
+
+fn parse(args: &[OsString]) -> Result<Args> {
+    let mut format = Format::Plain;
+    let mut args = args;
+    while let Some(arg) = args
+        .first()
+        .and_then(|arg| arg.to_str())
+        .filter(|arg| arg.starts_with("--"))
+    {
+        let name = match (arg, args.get(1)) {
+            ("--format", Some(name)) => {
+                args = &args[2..];
+                text(name)?
+            }
+            _ if arg.starts_with("--format=") => {
+                args = &args[1..];
+                &arg["--format=".len()..]
+            }
+            _ => return usage(&format!("unknown option '{}'", arg)),
+        };
+        format = name.parse().map_err(Error::Usage)?;
+    }
+    let (path, name, rest) = match args {
+        [path, name, rest @ ..] => (path, text(name)?, rest),
+        _ => return usage("missing file or command"),
+    };
+    let command = match (name, rest) {
+        ("get", [key]) => Command::Get(bytes(key)),
+        ("put", [key, value]) => Command::Put(bytes(key), bytes(value)),
+        ("del", [key]) => Command::Del(bytes(key)),
+        ("range", _) if rest.len() <= 2 => Command::Range(
+            rest.first()
+                .map_or(Bound::Unbounded, |start| Bound::Included(bytes(start))),
+            rest.get(1)
+                .map_or(Bound::Unbounded, |end| Bound::Excluded(bytes(end))),
+        ),
+        ("dump", []) => Command::Range(Bound::Unbounded, Bound::Unbounded),
+        ("stats", []) => Command::Stats,
+        ("get", _) | ("put", _) | ("del", _) | ("range", _) | ("dump", _) | ("stats", _) => {
+            return usage(&format!("wrong number of arguments to '{}'", name))
+        }
+        _ => return usage(&format!("unknown command '{}'", name)),
+    };
+    Ok(Args {
+        format,
+        path: PathBuf::from(path),
+        command,
+    })
+}
+
+/// Read the tree from a file, which is empty if the file does not
+/// exist.
+fn load(path: &Path) -> io::Result<Data> {
+    match File::open(path) {
+        Ok(file) => Tree::read_from(&mut BufReader::new(file)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(Tree::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Path of a file next to the data file, with a suffix added to the
+/// name of the data file.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Open the lock file, creating it if it does not exist.
+fn create_lock_file(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Lock the data file, which is exclusive if the tree is going to be
+/// changed. The lock is released when the returned file is closed.
+///
+/// A shared lock only needs to read the lock file. If it does not
+/// exist and cannot be created, nothing can change the data file
+/// either, so `None` is returned and the tree is read without a lock.
+fn lock(path: &Path, exclusive: bool) -> io::Result<Option<File>> {
+    let path = with_suffix(path, ".lock");
+    if exclusive {
+        let file = create_lock_file(&path)?;
+        file.lock()?;
+        return Ok(Some(file));
+    }
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => match create_lock_file(&path) {
+            Ok(file) => file,
+            Err(_) => return Ok(None),
+        },
+        Err(err) => return Err(err),
+    };
+    file.lock_shared()?;
+    Ok(Some(file))
+}
+
+/// Create a new temporary file next to the data file, with a name that
+/// no other file has.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, File)> {
+    let mut count = 0;
+    loop {
+        let suffix = format!(".{}.{}.tmp", process::id(), count);
+        let temp_path = with_suffix(path, &suffix);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => count += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Write the tree to a file and sync it.
+fn write_file(file: File, data: &Data) -> io::Result<()> {
+    let mut writer = BufWriter::new(file);
+    data.write_to(&mut writer)?;
+    let file = writer.into_inner().map_err(|err| err.into_error())?;
+    file.sync_all()
+}
+
+/// Replace the file with the tree.
+fn save(path: &Path, data: &Data) -> io::Result<()> {
+    let (temp_path, file) = create_temp(path)?;
+    if let Err(err) = write_file(file, data).and_then(|_| fs::rename(&temp_path, path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err);
+    }
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => sync_dir(dir),
+        _ => sync_dir(Path::new(".")),
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Run a command, returning false if the key was not found.
+fn run(args: Args) -> Result<bool> {
+    let path = args.path;
+    let io_error = |err| Error::Io(path.clone(), err);
+    let stdout_error = |err| Error::Io(PathBuf::from("<stdout>"), err);
+    let _lock = lock(&path, args.command.changes()).map_err(io_error)?;
+    let mut data = load(&path).map_err(io_error)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let found = match args.command {
+        Command::Get(key) => match data.get(&key) {
+            Some(value) => {
+                args.format.value(&mut out, value).map_err(stdout_error)?;
+                true
+            }
+            None => false,
+        },
+        Command::Put(key, value) => {
+            if data.get(&key) != Some(&value) {
+                data.insert(key, value);
+                save(&path, &data).map_err(io_error)?;
+            }
+            true
+        }
+        Command::Del(key) => {
+            let found = data.remove(&key).is_some();
+            if found {
+                save(&path, &data).map_err(io_error)?;
+            }
+            found
+        }
+        Command::Range(start, end) => {
+            let range = data.range((start.as_ref(), end.as_ref()));
+            args.format.entries(&mut out, range).map_err(stdout_error)?;
+            true
+        }
+        Command::Stats => {
+            let stats = data.stats();
+            args.format.stats(&mut out, &stats).map_err(stdout_error)?;
+            true
+        }
+    };
+    out.flush().map_err(stdout_error)?;
+    Ok(found)
+}
+
+fn main() {
+    let args: Vec<OsString> = env::args_os().skip(1).collect();
+    if let Some("-h") | Some("--help") = args.first().and_then(|arg| arg.to_str()) {
+        println!("{}", USAGE);
+        return;
+    }
+    match parse(&args).and_then(run) {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(err) => {
+            eprintln!("avl-kv: {}", err);
+            process::exit(2);
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Tests running the tool on a data file.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// Data file for a test, which is removed when dropped.
+struct DataFile(PathBuf);
+
+impl DataFile {
+    fn new(name: &str) -> DataFile {
+        let path = env::temp_dir().join(format!("avl-kv-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        DataFile(path)
+    }
+
+    /// Path of a file next to the data file.
+    fn with_suffix(&self, suffix: &str) -> PathBuf {
+        let mut path = self.0.clone().into_os_string();
+        path.push(suffix);
+        PathBuf::from(path)
+    }
+
+    fn command(&self, command: &[&str]) -> Command {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_avl-kv"));
+        process.arg(&self.0).args(command);
+        process
+    }
+
+    /// Run the tool, returning the exit status and the output.
+    fn run(&self, args: &[&str]) -> (i32, String) {
+        let (options, command) = match args.first() {
+            Some(&"--format") => args.split_at(2),
+            _ => args.split_at(0),
+        };
+        let output = Command::new(env!("CARGO_BIN_EXE_avl-kv"))
+            .args(options)
+            .arg(&self.0)
+            .args(command)
+            .output()
+            .expect("cannot run avl-kv");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        (output.status.code().unwrap(), stdout)
+    }
+}
+
+impl Drop for DataFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+        let _ = fs::remove_file(self.with_suffix(".lock"));
+    }
+}
+
+#[test]
+fn test_commands() {
+    let file = DataFile::new("commands");
+    assert_eq!(file.run(&["get", "a"]), (1, String::new()));
+    assert!(!file.0.exists());
+
+    for (key, value) in &[("b", "2"), ("a", "1"), ("c", "3"), ("d", "4")] {
+        assert_eq!(file.run(&["put", key, value]).0, 0);
+    }
+    assert_eq!(file.run(&["get", "b"]), (0, "2\n".to_string()));
+    assert_eq!(file.run(&["put", "b", "two"]).0, 0);
+    assert_eq!(file.run(&["del", "d"]).0, 0);
+    assert_eq!(file.run(&["del", "d"]).0, 1);
+
+    assert_eq!(file.run(&["dump"]), (0, "a: 1\nb: two\nc: 3\n".to_string()));
+    assert_eq!(file.run(&["range", "b"]), (0, "b: two\nc: 3\n".to_string()));
+    assert_eq!(
+        file.run(&["range", "a", "c"]),
+        (0, "a: 1\nb: two\n".to_string())
+    );
+    // No temporary file is left behind.
+    let prefix = file.0.file_name().unwrap().to_str().unwrap();
+    let temp_files = fs::read_dir(env::temp_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap_or_default())
+        .filter(|name| name.starts_with(prefix) && name.ends_with(".tmp"))
+        .count();
+    assert_eq!(temp_files, 0);
+}
+
+#[test]
+fn test_concurrent_changes() {
+    let file = DataFile::new("concurrent");
+    let keys: Vec<String> = (0..16).map(|key| format!("{:02}", key)).collect();
+    let children: Vec<_> = keys
+        .iter()
+        .map(|key| file.command(&["put", key, "x"]).spawn().unwrap())
+        .collect();
+    for mut child in children {
+        assert!(child.wait().unwrap().success());
+    }
+    let (status, dump) = file.run(&["dump"]);
+    assert_eq!(status, 0);
+    assert_eq!(dump.lines().count(), keys.len());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_bytes() {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    let file = DataFile::new("bytes");
+    let key = OsString::from_vec(vec![b'k', 0xFF]);
+    let output = file.command(&["put"]).arg(&key).arg("x").output().unwrap();
+    assert!(output.status.success());
+    let output = file.command(&["get"]).arg(&key).output().unwrap();
+    assert_eq!(output.stdout, b"x\n");
+    assert_eq!(file.run(&["get", "k"]).0, 1);
+}
+
+#[test]
+fn test_formats() {
+    let file = DataFile::new("formats");
+    file.run(&["put", "key", "tab\there"]);
+    file.run(&["put", "quote", "\"q\""]);
+    assert_eq!(
+        file.run(&["--format", "tsv", "dump"]).1,
+        "key\ttab\\there\nquote\t\"q\"\n"
+    );
+    assert_eq!(
+        file.run(&["--format", "json", "dump"]).1,
+        "[\n  {\"key\": \"key\", \"value\": \"tab\\there\"},\n  \
+         {\"key\": \"quote\", \"value\": \"\\\"q\\\"\"}\n]\n"
+    );
+    assert_eq!(
+        file.run(&["--format", "json", "get", "quote"]).1,
+        "\"\\\"q\\\"\"\n"
+    );
+}
+
+#[test]
+fn test_stats() {
+    let file = DataFile::new("stats");
+    for key in 0..7 {
+        file.run(&["put", &format!("{:02}", key), "x"]);
+    }
+    assert_eq!(
+        file.run(&["stats"]).1,
+        "height: 3\nnodes: 7\nbalance 0: 7\n"
+    );
+    assert_eq!(
+        file.run(&["--format", "json", "stats"]).1,
+        "{\"height\": 3, \"nodes\": 7, \"balance\": {\"0\": 7}}\n"
+    );
+}
+
+#[test]
+fn test_errors() {
+    let file = DataFile::new("errors");
+    assert_eq!(file.run(&["get"]).0, 2);
+    assert_eq!(file.run(&["frobnicate"]).0, 2);
+    assert_eq!(file.run(&["--format", "xml", "dump"]).0, 2);
+
+    fs::write(&file.0, b"not a tree").unwrap();
+    assert_eq!(file.run(&["dump"]).0, 2);
+    assert_eq!(file.run(&["put", "a", "1"]).0, 2);
+    assert_eq!(fs::read(&file.0).unwrap(), b"not a tree");
+}
+
+#[test]
+fn test_without_lock_file() {
+    // The lock file cannot be created in a missing directory, so the
+    // commands that only read the tree run without a lock and see an
+    // empty tree, while the others fail.
+    let file = DataFile::new("missing/data");
+    assert_eq!(file.run(&["get", "a"]).0, 1);
+    assert_eq!(file.run(&["dump"]), (0, String::new()));
+    assert_eq!(file.run(&["put", "a", "1"]).0, 2);
+}
@@ -0,0 +1,192 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Key-value server keeping the data in an AVL tree.
+//!
+//! Usage: `avl-server [--allow-shutdown] [address]`, where the address
+//! defaults to `127.0.0.1:4712`. The address the server listens on is
+//! printed on the first line of the output, which is useful with port
+//! 0.
+//!
+//! The server stops when it gets Ctrl-C, or a `SHUTDOWN` command if it
+//! was started with `--allow-shutdown`. Without that option any client
+//! could stop the server, so `SHUTDOWN` is rejected as an unknown
+//! command. The server then stops accepting connections, and closes
+//! each connection after answering the request that is being handled,
+//! if any.
+
+extern crate examples;
+extern crate futures;
+extern crate tokio;
+extern crate tokio_signal;
+
+mod protocol;
+
+use futures::future::Shared;
+use futures::sync::oneshot;
+use protocol::{Command, Error, Store};
+use std::env;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::codec::{Framed, LinesCodec};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::prelude::*;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:4712";
+
+/// Longest request line, to limit the memory used by a client.
+const MAX_LINE: usize = 1 << 20;
+
+/// Shared signal telling the tasks of the server to stop.
+#[derive(Clone)]
+struct Shutdown {
+    sender: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    receiver: Shared<oneshot::Receiver<()>>,
+}
+
+impl Shutdown {
+    fn new() -> Shutdown {
+        let (sender, receiver) = oneshot::channel();
+        Shutdown {
+            sender: Arc::new(Mutex::new(Some(sender))),
+            receiver: receiver.shared(),
+        }
+    }
+
+    fn trigger(&self) {
+        if let Some(sender) = self.sender.lock().unwrap().take() {
+            // The receiver is kept alive by this object, so sending
+            // cannot fail.
+            let _ = sender.send(());
+        }
+    }
+
+    /// Future completing when the server is stopping.
+    fn wait(&self) -> impl Future<Item = (), Error = ()> {
+        self.receiver.clone().then(|_| Ok(()))
+    }
+}
+
+/// Shared state of the server.
+struct State {
+    store: RwLock<Store>,
+    allow_shutdown: bool,
+}
+
+fn respond(state: &State, shutdown: &Shutdown, line: &str) -> String {
+    let store = &state.store;
+    match protocol::parse(line) {
+        Err(error) => format!("ERROR {}", error),
+        Ok(Command::Shutdown) if !state.allow_shutdown => {
+            let error = Error::UnknownCommand("SHUTDOWN".to_string());
+            format!("ERROR {}", error)
+        }
+        Ok(Command::Shutdown) => {
+            shutdown.trigger();
+            "OK".to_string()
+        }
+        Ok(command @ Command::Put(..)) | Ok(command @ Command::Del(..)) => {
+            protocol::write(&mut store.write().unwrap(), command)
+        }
+        Ok(command) => protocol::read(&store.read().unwrap(), &command),
+    }
+}
+
+/// Answer the requests of a client until it disconnects or the server
+/// stops.
+fn serve_client(
+    socket: TcpStream,
+    state: Arc<State>,
+    shutdown: Shutdown,
+) -> impl Future<Item = (), Error = ()> {
+    let (sink, lines) = Framed::new(socket, LinesCodec::new_with_max_length(MAX_LINE)).split();
+    // The request lines are followed by `None` when the server stops,
+    // which ends the stream.
+    let stop = shutdown.wait().then(|_| Ok(None)).into_stream();
+    let responses = lines
+        .map(Some)
+        .select(stop)
+        .take_while(|line| Ok(line.is_some()))
+        .map(move |line| respond(&state, &shutdown, &line.unwrap()));
+    sink.send_all(responses)
+        .map(|_| ())
+        .map_err(|error| eprintln!("client error: {}", error))
+}
+
+/// Future completing when the process gets Ctrl-C, or never if that
+/// cannot be detected.
+fn ctrl_c() -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let signal =
+        tokio_signal::ctrl_c()
+            .flatten_stream()
+            .into_future()
+            .then(|result| match result {
+                Ok(_) => Box::new(future::ok(())) as Box<dyn Future<Item = (), Error = ()> + Send>,
+                Err((error, _)) => {
+                    eprintln!("cannot wait for Ctrl-C: {}", error);
+                    Box::new(future::empty())
+                }
+            });
+    Box::new(signal)
+}
+
+fn serve(listener: TcpListener, allow_shutdown: bool) -> impl Future<Item = (), Error = ()> {
+    let state = Arc::new(State {
+        store: RwLock::new(Store::default()),
+        allow_shutdown,
+    });
+    let shutdown = Shutdown::new();
+
+    let accept = {
+        let shutdown = shutdown.clone();
+        listener
+            .incoming()
+            .map_err(|error| eprintln!("accept error: {}", error))
+            .for_each(move |socket| {
+                tokio::spawn(serve_client(socket, state.clone(), shutdown.clone()));
+                Ok(())
+            })
+    };
+    let signal = {
+        let shutdown = shutdown.clone();
+        ctrl_c().map(move |_| shutdown.trigger())
+    };
+    // The runtime waits for the clients to finish after the accept
+    // loop stops.
+    accept
+        .select(shutdown.wait())
+        .map(|_| ())
+        .map_err(|_| ())
+        .select(signal)
+        .map(|_| ())
+        .map_err(|_| ())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let allow_shutdown = args.first().map(String::as_str) == Some("--allow-shutdown");
+    if allow_shutdown {
+        args.remove(0);
+    }
+    let address = args
+        .first()
+        .map_or(DEFAULT_ADDRESS, String::as_str)
+        .parse::<SocketAddr>()?;
+    let listener = TcpListener::bind(&address)?;
+    println!("listening on {}", listener.local_addr()?);
+    io::stdout().flush()?;
+    tokio::run(serve(listener, allow_shutdown));
+    Ok(())
+}
@@ -0,0 +1,277 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Line protocol of the server.
+//!
+//! Each request is a line with a command and its arguments separated
+//! by spaces, and each response is one or more lines:
+//!
+//! ```text
+//! GET key             VALUE value | NOT_FOUND
+//! PUT key value       OK
+//! DEL key             OK | NOT_FOUND
+//! RANGE [start [end]] ENTRY key value ... END
+//! COUNT [start [end]] COUNT n
+//! SHUTDOWN            OK
+//! ```
+//!
+//! A range includes the start and excludes the end, and a missing
+//! bound or `*` means that the range is not bounded on that side.
+//! Errors are reported as `ERROR message`.
+//!
+//! Keys and values are byte strings, where bytes that are not
+//! printable ASCII, as well as space, `%` and `*`, are written as `%`
+//! followed by two hex digits. The empty byte string is written as a
+//! single `%`, so that it is still a token.
+//!
+//! `SHUTDOWN` is only accepted if the server was started with
+//! `--allow-shutdown`.
+
+use examples::avl::tree::{Size, Tree};
+use std::fmt;
+use std::ops::Bound;
+
+/// Tree holding the data of the server. The `Size` summary is used
+/// to count the entries in a range.
+pub type Store = Tree<Vec<u8>, Vec<u8>, Size>;
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    UnknownCommand(String),
+    /// Wrong number of arguments, with the usage of the command.
+    Usage(&'static str),
+    BadEscape(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownCommand(ref name) => write!(f, "unknown command '{}'", name),
+            Error::Usage(usage) => write!(f, "usage: {}", usage),
+            Error::BadEscape(ref token) => write!(f, "bad escape in '{}'", token),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Get(Vec<u8>),
+    Put(Vec<u8>, Vec<u8>),
+    Del(Vec<u8>),
+    Range(Bound<Vec<u8>>, Bound<Vec<u8>>),
+    Count(Bound<Vec<u8>>, Bound<Vec<u8>>),
+    Shutdown,
+}
+
+/// Write a byte string as a token.
+pub fn encode(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "%".to_string();
+    }
+    let mut token = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'%' | b'*' => token.push_str(&format!("%{:02X}", byte)),
+            b'!'..=b'~' => token.push(byte as char),
+            _ => token.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    token
+}
+
+/// Read a byte string from a token.
+pub fn decode(token: &str) -> Result<Vec<u8>> {
+    if token == "%" {
+        return Ok(Vec::new());
+    }
+    let bad_escape = || Error::BadEscape(token.to_string());
+    let mut bytes = Vec::with_capacity(token.len());
+    let mut rest = token.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).ok_or_else(bad_escape)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| bad_escape())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| bad_escape())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(bytes)
+}
+
+/// Read a bound of a range, where `*` or a missing token means no
+/// bound.
+fn bound<F>(token: Option<&str>, bounded: F) -> Result<Bound<Vec<u8>>>
+where
+    F: FnOnce(Vec<u8>) -> Bound<Vec<u8>>,
+{
+    match token {
+        None | Some("*") => Ok(Bound::Unbounded),
+        Some(token) => Ok(bounded(decode(token)?)),
+    }
+}
+
+pub fn parse(line: &str) -> Result<Command> {
+    let mut tokens = line.split_whitespace();
+    let name = match tokens.next() {
+        None => return Err(Error::UnknownCommand(String::new())),
+        Some(name) => name.to_ascii_uppercase(),
+    };
+    let args: Vec<&str> = tokens.collect();
+    match (name.as_str(), args.as_slice()) {
+        ("GET", [key]) => Ok(Command::Get(decode(key)?)),
+        ("GET", _) => Err(Error::Usage("GET key")),
+        ("PUT", [key, value]) => Ok(Command::Put(decode(key)?, decode(value)?)),
+        ("PUT", _) => Err(Error::Usage("PUT key value")),
+        ("DEL", [key]) => Ok(Command::Del(decode(key)?)),
+        ("DEL", _) => Err(Error::Usage("DEL key")),
+        ("RANGE", _) | ("COUNT", _) if args.len() > 2 => {
+            Err(Error::Usage("RANGE|COUNT [start [end]]"))
+        }
+        ("RANGE", _) | ("COUNT", _) => {
+            let start = bound(args.first().cloned(), Bound::Included)?;
+            let end = bound(args.get(1).cloned(), Bound::Excluded)?;
+            if name == "RANGE" {
+                Ok(Command::Range(start, end))
+            } else {
+                Ok(Command::Count(start, end))
+            }
+        }
+        ("SHUTDOWN", []) => Ok(Command::Shutdown),
+        ("SHUTDOWN", _) => Err(Error::Usage("SHUTDOWN")),
+        _ => Err(Error::UnknownCommand(name)),
+    }
+}
+
+/// Number of entries with keys in a range.
+fn count(store: &Store, start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>) -> usize {
+    let contains = |key| store.get(key).is_some() as usize;
+    let before_start = match start {
+        Bound::Unbounded => 0,
+        Bound::Included(key) => store.rank(key),
+        Bound::Excluded(key) => store.rank(key) + contains(key),
+    };
+    let before_end = match end {
+        Bound::Unbounded => store.len(),
+        Bound::Included(key) => store.rank(key) + contains(key),
+        Bound::Excluded(key) => store.rank(key),
+    };
+    before_end.saturating_sub(before_start)
+}
+
+/// Run a command that only reads the store, returning the response.
+pub fn read(store: &Store, command: &Command) -> String {
+    match command {
+        Command::Get(key) => match store.get(key) {
+            None => "NOT_FOUND".to_string(),
+            Some(value) => format!("VALUE {}", encode(value)),
+        },
+        Command::Range(start, end) => {
+            let mut response = String::new();
+            let range = (start.as_ref(), end.as_ref());
+            for (key, value) in store.range(range) {
+                response.push_str(&format!("ENTRY {} {}\n", encode(key), encode(value)));
+            }
+            response.push_str("END");
+            response
+        }
+        Command::Count(start, end) => format!("COUNT {}", count(store, start, end)),
+        _ => panic!("not a read command: {:?}", command),
+    }
+}
+
+/// Run a command that changes the store, returning the response.
+pub fn write(store: &mut Store, command: Command) -> String {
+    match command {
+        Command::Put(key, value) => {
+            store.insert(key, value);
+            "OK".to_string()
+        }
+        Command::Del(key) => match store.remove(&key) {
+            None => "NOT_FOUND".to_string(),
+            Some(_) => "OK".to_string(),
+        },
+        _ => panic!("not a write command: {:?}", command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escapes() {
+        let bytes = b"a b%c*\n\xff";
+        assert_eq!(encode(bytes), "a%20b%25c%2A%0A%FF");
+        assert_eq!(decode(&encode(bytes)), Ok(bytes.to_vec()));
+        assert_eq!(encode(b""), "%");
+        assert_eq!(decode("%"), Ok(Vec::new()));
+        assert_eq!(decode("a%"), Err(Error::BadEscape("a%".to_string())));
+        assert_eq!(decode("%4"), Err(Error::BadEscape("%4".to_string())));
+        assert_eq!(decode("%zz"), Err(Error::BadEscape("%zz".to_string())));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("get a"), Ok(Command::Get(b"a".to_vec())));
+        assert_eq!(
+            parse("PUT a%20b c"),
+            Ok(Command::Put(b"a b".to_vec(), b"c".to_vec()))
+        );
+        assert_eq!(
+            parse("RANGE * m"),
+            Ok(Command::Range(
+                Bound::Unbounded,
+                Bound::Excluded(b"m".to_vec())
+            ))
+        );
+        assert_eq!(
+            parse("COUNT a"),
+            Ok(Command::Count(
+                Bound::Included(b"a".to_vec()),
+                Bound::Unbounded
+            ))
+        );
+        assert_eq!(
+            parse("PUT % a"),
+            Ok(Command::Put(Vec::new(), b"a".to_vec()))
+        );
+        assert_eq!(parse("PUT a"), Err(Error::Usage("PUT key value")));
+        assert_eq!(parse("FOO"), Err(Error::UnknownCommand("FOO".to_string())));
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut store = Store::default();
+        for key in &["a", "b", "c", "d"] {
+            let command = parse(&format!("PUT {} {}", key, key.to_uppercase()));
+            assert_eq!(write(&mut store, command.unwrap()), "OK");
+        }
+        let read_line = |line: &str| read(&store, &parse(line).unwrap());
+        assert_eq!(read_line("GET b"), "VALUE B");
+        assert_eq!(read_line("GET x"), "NOT_FOUND");
+        assert_eq!(read_line("RANGE b d"), "ENTRY b B\nENTRY c C\nEND");
+        assert_eq!(read_line("RANGE e"), "END");
+        assert_eq!(read_line("COUNT"), "COUNT 4");
+        assert_eq!(read_line("COUNT b d"), "COUNT 2");
+        assert_eq!(read_line("COUNT bb"), "COUNT 2");
+        assert_eq!(read_line("COUNT d b"), "COUNT 0");
+        assert_eq!(write(&mut store, parse("DEL a").unwrap()), "OK");
+        assert_eq!(write(&mut store, parse("DEL a").unwrap()), "NOT_FOUND");
+    }
+}
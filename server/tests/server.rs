@@ -0,0 +1,193 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Tests running the server and talking to it over TCP.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Server running on a free port on localhost, which is killed when
+/// dropped if it did not stop by itself.
+struct Server {
+    process: Child,
+    address: String,
+}
+
+impl Server {
+    fn start() -> Server {
+        Server::start_with(&[])
+    }
+
+    fn start_with(options: &[&str]) -> Server {
+        let mut process = Command::new(env!("CARGO_BIN_EXE_avl-server"))
+            .args(options)
+            .arg("127.0.0.1:0")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("cannot start server");
+        let mut line = String::new();
+        let stdout = process.stdout.take().unwrap();
+        BufReader::new(stdout).read_line(&mut line).unwrap();
+        let address = line.trim().trim_start_matches("listening on ").to_string();
+        Server { process, address }
+    }
+
+    fn connect(&self) -> Client {
+        let stream = TcpStream::connect(&self.address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        Client {
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        }
+    }
+
+    /// Wait for the server to stop, returning false if it did not.
+    fn stopped(&mut self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            if let Some(status) = self.process.try_wait().unwrap() {
+                return status.success();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        line.trim_end().to_string()
+    }
+
+    /// Send a request and read the first line of the response.
+    fn request(&mut self, request: &str) -> String {
+        let line = format!("{}\n", request);
+        self.writer.write_all(line.as_bytes()).unwrap();
+        self.line()
+    }
+}
+
+#[test]
+fn commands() {
+    let server = Server::start();
+    let mut client = server.connect();
+    assert_eq!(client.request("PUT b 2"), "OK");
+    assert_eq!(client.request("PUT a 1"), "OK");
+    assert_eq!(client.request("PUT c%20d %FF"), "OK");
+    assert_eq!(client.request("GET a"), "VALUE 1");
+    assert_eq!(client.request("GET c%20d"), "VALUE %FF");
+    assert_eq!(client.request("GET x"), "NOT_FOUND");
+    assert_eq!(client.request("PUT empty %"), "OK");
+    assert_eq!(client.request("GET empty"), "VALUE %");
+    assert_eq!(client.request("DEL empty"), "OK");
+    assert_eq!(client.request("COUNT"), "COUNT 3");
+    assert_eq!(client.request("COUNT b"), "COUNT 2");
+
+    assert_eq!(client.request("RANGE a c"), "ENTRY a 1");
+    assert_eq!(client.line(), "ENTRY b 2");
+    assert_eq!(client.line(), "END");
+
+    assert_eq!(client.request("DEL b"), "OK");
+    assert_eq!(client.request("DEL b"), "NOT_FOUND");
+    assert_eq!(client.request("RANGE * b"), "ENTRY a 1");
+    assert_eq!(client.line(), "END");
+    assert_eq!(client.request("PUT a"), "ERROR usage: PUT key value");
+    assert_eq!(client.request("FROB"), "ERROR unknown command 'FROB'");
+}
+
+#[test]
+fn concurrent_clients() {
+    let server = Server::start();
+    let threads: Vec<_> = (0..8)
+        .map(|id| {
+            let mut client = server.connect();
+            thread::spawn(move || {
+                for key in 0..100 {
+                    let request = format!("PUT {}-{:03} {}", id, key, key);
+                    assert_eq!(client.request(&request), "OK");
+                }
+                assert_eq!(
+                    client.request(&format!("COUNT {} {}.", id, id)),
+                    "COUNT 100"
+                );
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(server.connect().request("COUNT"), "COUNT 800");
+}
+
+#[test]
+fn shutdown_disabled() {
+    let mut server = Server::start();
+    let mut client = server.connect();
+    assert_eq!(
+        client.request("SHUTDOWN"),
+        "ERROR unknown command 'SHUTDOWN'"
+    );
+    assert_eq!(client.request("PUT a 1"), "OK");
+    assert!(!server.stopped(Duration::from_millis(100)));
+}
+
+#[cfg(unix)]
+#[test]
+fn ctrl_c() {
+    let mut server = Server::start();
+    let mut client = server.connect();
+    assert_eq!(client.request("PUT a 1"), "OK");
+    let status = Command::new("kill")
+        .arg("-INT")
+        .arg(server.process.id().to_string())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(client.line(), "");
+    assert!(server.stopped(Duration::from_secs(10)));
+}
+
+#[test]
+fn graceful_shutdown() {
+    let mut server = Server::start_with(&["--allow-shutdown"]);
+    let mut idle = server.connect();
+    let mut client = server.connect();
+    assert_eq!(idle.request("PUT a 1"), "OK");
+    assert_eq!(client.request("SHUTDOWN"), "OK");
+
+    // Open connections are closed and the server exits by itself.
+    assert_eq!(idle.line(), "");
+    assert_eq!(client.line(), "");
+    assert!(server.stopped(Duration::from_secs(10)));
+}
@@ -0,0 +1,142 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Bidirectional map built on two AVL trees.
+//!
+//! The map is a one-to-one relation between left and right values,
+//! kept as one tree from left to right and one from right to left.
+//! Every change is applied to both trees, so they always hold the
+//! same pairs.
+
+use super::tree::{self, Tree};
+
+pub struct BiMap<L, R> {
+    left: Tree<L, R>,
+    right: Tree<R, L>,
+}
+
+impl<L, R> Default for BiMap<L, R> {
+    fn default() -> BiMap<L, R> {
+        BiMap {
+            left: Tree::default(),
+            right: Tree::default(),
+        }
+    }
+}
+
+impl<L, R> BiMap<L, R>
+where
+    L: PartialOrd + Clone,
+    R: PartialOrd + Clone,
+{
+    pub fn new() -> BiMap<L, R> {
+        BiMap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.left.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Insert a pair, removing any existing pairs with the same left
+    /// or right value.
+    ///
+    /// Returns the right value that was paired with the left value
+    /// and the left value that was paired with the right value.
+    pub fn insert(&mut self, left: L, right: R) -> (Option<R>, Option<L>) {
+        let old_right = self.remove_by_left(&left).map(|(_, r)| r);
+        let old_left = self.remove_by_right(&right).map(|(l, _)| l);
+        self.left.insert(left.clone(), right.clone());
+        self.right.insert(right, left);
+        (old_right, old_left)
+    }
+
+    pub fn get_by_left(&self, left: &L) -> Option<&R> {
+        self.left.get(left)
+    }
+
+    pub fn get_by_right(&self, right: &R) -> Option<&L> {
+        self.right.get(right)
+    }
+
+    pub fn contains_left(&self, left: &L) -> bool {
+        self.left.get(left).is_some()
+    }
+
+    pub fn contains_right(&self, right: &R) -> bool {
+        self.right.get(right).is_some()
+    }
+
+    /// Remove the pair with a left value, returning the pair.
+    pub fn remove_by_left(&mut self, left: &L) -> Option<(L, R)> {
+        let right = self.left.remove(left)?;
+        let left = self.right.remove(&right).expect("trees out of sync");
+        Some((left, right))
+    }
+
+    /// Remove the pair with a right value, returning the pair.
+    pub fn remove_by_right(&mut self, right: &R) -> Option<(L, R)> {
+        let left = self.right.remove(right)?;
+        let right = self.left.remove(&left).expect("trees out of sync");
+        Some((left, right))
+    }
+
+    /// Iterate over the pairs ordered by left value.
+    pub fn iter(&self) -> tree::Iter<'_, L, R> {
+        self.left.iter()
+    }
+
+    /// Iterate over the pairs ordered by right value, with the right
+    /// value first.
+    pub fn iter_by_right(&self) -> tree::Iter<'_, R, L> {
+        self.right.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let mut map = BiMap::new();
+        map.insert(1, "one");
+        map.insert(2, "two");
+        map.insert(3, "three");
+        assert_eq!(map.get_by_left(&2), Some(&"two"));
+        assert_eq!(map.get_by_right(&"three"), Some(&3));
+        assert_eq!(map.remove_by_right(&"two"), Some((2, "two")));
+        assert!(!map.contains_left(&2));
+        assert_eq!(map.len(), 2);
+
+        let by_right: Vec<_> = map.iter_by_right().map(|(r, l)| (*r, *l)).collect();
+        assert_eq!(by_right, vec![("one", 1), ("three", 3)]);
+    }
+
+    #[test]
+    fn test_overwrite() {
+        let mut map = BiMap::new();
+        assert_eq!(map.insert(1, 'a'), (None, None));
+        assert_eq!(map.insert(2, 'b'), (None, None));
+        // Both existing pairs are replaced by the new pair.
+        assert_eq!(map.insert(1, 'b'), (Some('a'), Some(2)));
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get_by_left(&1), Some(&'b'));
+        assert_eq!(map.get_by_right(&'a'), None);
+        assert_eq!(map.get_by_left(&2), None);
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Cache with expiring entries and a bounded size.
+//!
+//! Entries are kept in a tree by key. Two more trees index the keys
+//! by deadline, so expired entries are found without a scan, and by
+//! the order in which they will be evicted when the cache is full.
+
+use super::time::Timestamp;
+use super::tree::Tree;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for a cache.
+pub trait Clock {
+    fn now(&self) -> Timestamp;
+}
+
+/// Clock giving the system time in milliseconds since the Unix epoch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as Timestamp)
+    }
+}
+
+/// Clock that only moves when told to, for tests and simulations.
+///
+/// Clones share the same time, so a clone can be kept to move the
+/// clock of a cache.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock(Rc<Cell<Timestamp>>);
+
+impl ManualClock {
+    pub fn set(&self, now: Timestamp) {
+        self.0.set(now);
+    }
+
+    pub fn advance(&self, delta: Timestamp) {
+        self.0.set(self.0.get() + delta);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Timestamp {
+        self.0.get()
+    }
+}
+
+/// Which entry to evict when the cache is full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eviction {
+    /// Evict the entry that was least recently inserted or read.
+    Lru,
+    /// Evict the entry that was least recently inserted.
+    OldestFirst,
+}
+
+struct Entry<V> {
+    value: V,
+    deadline: Option<Timestamp>,
+    /// Position of the entry in the eviction order.
+    tick: u64,
+}
+
+pub struct Cache<K, V, C = SystemClock> {
+    entries: Tree<K, Entry<V>>,
+    /// Keys by deadline, with the tick to tell equal deadlines apart.
+    deadlines: Tree<(Timestamp, u64), K>,
+    /// Keys in eviction order.
+    order: Tree<u64, K>,
+    next_tick: u64,
+    capacity: Option<usize>,
+    eviction: Eviction,
+    clock: C,
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: PartialOrd + Clone,
+{
+    pub fn new() -> Cache<K, V> {
+        Cache::with_clock(SystemClock)
+    }
+}
+
+impl<K, V> Default for Cache<K, V>
+where
+    K: PartialOrd + Clone,
+{
+    fn default() -> Cache<K, V> {
+        Cache::new()
+    }
+}
+
+impl<K, V, C> Cache<K, V, C>
+where
+    K: PartialOrd + Clone,
+    C: Clock,
+{
+    /// Create an unbounded cache that reads the time from a clock.
+    pub fn with_clock(clock: C) -> Cache<K, V, C> {
+        Cache {
+            entries: Tree::new(),
+            deadlines: Tree::new(),
+            order: Tree::new(),
+            next_tick: 0,
+            capacity: None,
+            eviction: Eviction::Lru,
+            clock,
+        }
+    }
+
+    /// Set the largest number of entries, or `None` for no limit.
+    ///
+    /// If the cache holds more entries than that, expired entries are
+    /// purged and then entries are evicted right away.
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+        self.enforce_capacity();
+    }
+
+    /// Set which entries are evicted when the cache is full. The
+    /// default is `Eviction::Lru`.
+    pub fn set_eviction(&mut self, eviction: Eviction) {
+        self.eviction = eviction;
+    }
+
+    /// Number of entries, including expired entries that have not
+    /// been purged yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Insert an entry that does not expire, returning the old value
+    /// if the key was present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.insert_entry(key, value, None)
+    }
+
+    /// Insert an entry that expires after a time to live, returning
+    /// the old value if the key was present.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Timestamp) -> Option<V> {
+        let deadline = self.clock.now().saturating_add(ttl);
+        self.insert_entry(key, value, Some(deadline))
+    }
+
+    fn insert_entry(&mut self, key: K, value: V, deadline: Option<Timestamp>) -> Option<V> {
+        let old = self.remove(&key);
+        let tick = self.tick();
+        if let Some(deadline) = deadline {
+            self.deadlines.insert((deadline, tick), key.clone());
+        }
+        self.order.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                deadline,
+                tick,
+            },
+        );
+        self.enforce_capacity();
+        old
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    fn enforce_capacity(&mut self) {
+        if let Some(capacity) = self.capacity {
+            if self.len() > capacity {
+                let now = self.clock.now();
+                self.purge_expired(now);
+            }
+            while self.len() > capacity {
+                let key = match self.order.iter().next() {
+                    Some((_, key)) => key.clone(),
+                    None => break,
+                };
+                self.remove(&key);
+            }
+        }
+    }
+
+    /// Get the value of an entry that has not expired.
+    ///
+    /// An expired entry is removed. With `Eviction::Lru`, the entry
+    /// becomes the most recently used.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        let (deadline, tick) = match self.entries.get(key) {
+            None => return None,
+            Some(entry) => (entry.deadline, entry.tick),
+        };
+        if deadline.is_some_and(|deadline| deadline <= now) {
+            self.remove(key);
+            return None;
+        }
+        if self.eviction == Eviction::Lru {
+            let new_tick = self.tick();
+            self.order.remove(&tick);
+            self.order.insert(new_tick, key.clone());
+            if let Some(deadline) = deadline {
+                self.deadlines.remove(&(deadline, tick));
+                self.deadlines.insert((deadline, new_tick), key.clone());
+            }
+            self.entries.get_mut(key).unwrap().tick = new_tick;
+        }
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    /// Get the value of an entry that has not expired, without
+    /// changing the eviction order.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        let now = self.clock.now();
+        self.entries
+            .get(key)
+            .filter(|entry| entry.deadline.is_none_or(|deadline| deadline > now))
+            .map(|entry| &entry.value)
+    }
+
+    /// Remove an entry, returning its value if it was present, even
+    /// if it expired.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.tick);
+        if let Some(deadline) = entry.deadline {
+            self.deadlines.remove(&(deadline, entry.tick));
+        }
+        Some(entry.value)
+    }
+
+    /// Remove all entries with a deadline at or before a time,
+    /// returning how many were removed.
+    pub fn purge_expired(&mut self, now: Timestamp) -> usize {
+        let expired: Vec<K> = self
+            .deadlines
+            .range(..=(now, u64::MAX))
+            .map(|(_, key)| key.clone())
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+        expired.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl() {
+        let clock = ManualClock::default();
+        let mut cache = Cache::with_clock(clock.clone());
+        cache.insert_with_ttl("a", 1, 10);
+        cache.insert_with_ttl("b", 2, 20);
+        cache.insert("c", 3);
+
+        clock.set(9);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        clock.set(10);
+        assert_eq!(cache.peek(&"a"), None);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.len(), 2);
+
+        // Inserting again resets the deadline.
+        cache.insert_with_ttl("b", 4, 20);
+        assert_eq!(cache.purge_expired(29), 0);
+        assert_eq!(cache.purge_expired(1000), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn test_lru() {
+        let clock = ManualClock::default();
+        let mut cache = Cache::with_clock(clock.clone());
+        cache.set_capacity(Some(2));
+        cache.insert(1, "one");
+        cache.insert(2, "two");
+        assert_eq!(cache.get(&1), Some(&"one"));
+        cache.insert(3, "three");
+        assert_eq!(cache.peek(&2), None);
+        assert_eq!(cache.peek(&1), Some(&"one"));
+
+        // Expired entries are purged before live entries are evicted.
+        cache.insert_with_ttl(4, "four", 5);
+        clock.advance(5);
+        cache.insert(5, "five");
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.peek(&1), None);
+        assert_eq!(cache.peek(&3), Some(&"three"));
+        assert_eq!(cache.peek(&5), Some(&"five"));
+    }
+
+    #[test]
+    fn test_oldest_first() {
+        let mut cache = Cache::with_clock(ManualClock::default());
+        cache.set_eviction(Eviction::OldestFirst);
+        for key in 0..5 {
+            cache.insert(key, key);
+            cache.get(&0);
+        }
+        cache.set_capacity(Some(3));
+        let keys: Vec<_> = (0..5).filter(|key| cache.peek(key).is_some()).collect();
+        assert_eq!(keys, vec![2, 3, 4]);
+    }
+}
@@ -0,0 +1,391 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Compact binary file format for trees.
+//!
+//! A tree is stored as a header, the entries in key order, and a
+//! trailing checksum:
+//!
+//! ```text
+//! magic     4 bytes  "AVLT"
+//! version   u32      format version, currently 1
+//! count     u64      number of entries
+//! entries   count * (key, value), encoded using `Codec`
+//! checksum  u32      CRC-32 of all preceding bytes
+//! ```
+//!
+//! All integers are stored in little-endian byte order. Since the
+//! entries are stored in sorted order, the tree can be rebuilt in
+//! linear time when reading it back.
+
+use super::tree::{Summary, Tree};
+use std::cmp::Ordering;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 4] = b"AVLT";
+const VERSION: u32 = 1;
+
+/// Encoding and decoding of keys and values.
+pub trait Codec: Sized {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! impl_codec_for_number {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_for_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl Codec for usize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u64).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let value = u64::decode(reader)?;
+        if value > usize::MAX as u64 {
+            return Err(invalid_data("usize out of range"));
+        }
+        Ok(value as usize)
+    }
+}
+
+impl Codec for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("bad boolean")),
+        }
+    }
+}
+
+impl Codec for () {
+    fn encode<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn decode<R: Read>(_reader: &mut R) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl Codec for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (self.len() as u64).encode(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let bytes = read_bytes(reader)?;
+        String::from_utf8(bytes).map_err(|_| invalid_data("bad UTF-8 string"))
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (self.len() as u64).encode(writer)?;
+        for item in self {
+            item.encode(writer)?;
+        }
+        Ok(())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let len = u64::decode(reader)?;
+        let mut result = Vec::new();
+        for _ in 0..len {
+            result.push(T::decode(reader)?);
+        }
+        Ok(result)
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            None => false.encode(writer),
+            Some(value) => {
+                true.encode(writer)?;
+                value.encode(writer)
+            }
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if bool::decode(reader)? {
+            Ok(Some(T::decode(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.encode(writer)?;
+        self.1.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok((A::decode(reader)?, B::decode(reader)?))
+    }
+}
+
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Read a length-prefixed byte string.
+///
+/// The bytes are read incrementally rather than allocating the full
+/// length up front, so a corrupted length cannot cause a huge
+/// allocation.
+fn read_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = u64::decode(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC-32 (IEEE) checksum.
+#[derive(Clone, Copy)]
+pub(crate) struct Crc32(u32);
+
+impl Crc32 {
+    pub(crate) fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ *byte as u32) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    pub(crate) fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Reader or writer that computes the checksum of all bytes passing
+/// through it.
+pub(crate) struct Checksummed<T> {
+    pub(crate) inner: T,
+    pub(crate) crc: Crc32,
+}
+
+impl<T> Checksummed<T> {
+    pub(crate) fn new(inner: T) -> Checksummed<T> {
+        Checksummed {
+            inner,
+            crc: Crc32::new(),
+        }
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.inner.write(buf)?;
+        self.crc.update(&buf[..count]);
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.crc.update(&buf[..count]);
+        Ok(count)
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: Codec + PartialOrd,
+    V: Codec,
+    S: Summary<K, V>,
+{
+    /// Write the tree to a writer in the binary format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut writer = Checksummed::new(writer);
+        writer.write_all(MAGIC)?;
+        VERSION.encode(&mut writer)?;
+        (self.len() as u64).encode(&mut writer)?;
+        for (key, value) in self.iter() {
+            key.encode(&mut writer)?;
+            value.encode(&mut writer)?;
+        }
+        let checksum = writer.crc.finish();
+        checksum.encode(&mut writer.inner)
+    }
+
+    /// Read a tree in the binary format from a reader.
+    ///
+    /// Fails with `InvalidData` if the header, the order of the
+    /// entries, or the checksum is wrong.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Tree<K, V, S>> {
+        let mut reader = Checksummed::new(reader);
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != *MAGIC {
+            return Err(invalid_data("not a tree file"));
+        }
+        let version = u32::decode(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data("unsupported tree file version"));
+        }
+
+        let count = u64::decode(&mut reader)?;
+        let mut entries: Vec<(K, V)> = Vec::new();
+        for _ in 0..count {
+            let key = K::decode(&mut reader)?;
+            let value = V::decode(&mut reader)?;
+            if let Some((last, _)) = entries.last() {
+                if key.partial_cmp(last) != Some(Ordering::Greater) {
+                    return Err(invalid_data("entries not in sorted order"));
+                }
+            }
+            entries.push((key, value));
+        }
+
+        let expected = reader.crc.finish();
+        if u32::decode(&mut reader.inner)? != expected {
+            return Err(invalid_data("checksum mismatch"));
+        }
+        Ok(Tree::from_sorted(entries))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Tree<u32, String> {
+        let mut tree = Tree::new();
+        for i in 0..100 {
+            tree.insert((i * 37) % 100, format!("value {}", i));
+        }
+        tree
+    }
+
+    #[test]
+    fn test_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"123456789");
+        assert_eq!(crc.finish(), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let tree = sample();
+        let mut buffer = Vec::new();
+        tree.write_to(&mut buffer).unwrap();
+        let copy: Tree<u32, String> = Tree::read_from(&mut buffer.as_slice()).unwrap();
+        assert_eq!(copy.len(), tree.len());
+        assert!(copy.iter().eq(tree.iter()));
+        assert!(copy.height() <= 7);
+
+        let empty: Tree<u32, String> = Tree::new();
+        let mut buffer = Vec::new();
+        empty.write_to(&mut buffer).unwrap();
+        let copy: Tree<u32, String> = Tree::read_from(&mut buffer.as_slice()).unwrap();
+        assert!(copy.is_empty());
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut buffer = Vec::new();
+        sample().write_to(&mut buffer).unwrap();
+
+        let mut corrupted = buffer.clone();
+        corrupted[30] ^= 0x01;
+        let error = Tree::<u32, String>::read_from(&mut corrupted.as_slice()).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+        let mut corrupted = buffer.clone();
+        corrupted[0] = b'X';
+        let error = Tree::<u32, String>::read_from(&mut corrupted.as_slice()).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+
+        let truncated = &buffer[..buffer.len() - 10];
+        let error = Tree::<u32, String>::read_from(&mut &truncated[..]).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn test_unsorted() {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(MAGIC);
+        buffer.extend_from_slice(&VERSION.to_le_bytes());
+        buffer.extend_from_slice(&2u64.to_le_bytes());
+        for key in &[2u32, 1u32] {
+            buffer.extend_from_slice(&key.to_le_bytes());
+            buffer.push(0);
+        }
+        let mut crc = Crc32::new();
+        crc.update(&buffer);
+        buffer.extend_from_slice(&crc.finish().to_le_bytes());
+        let error = Tree::<u32, bool>::read_from(&mut buffer.as_slice()).err();
+        assert_eq!(error.map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
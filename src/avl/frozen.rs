@@ -0,0 +1,303 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Read-only copy of an AVL tree laid out for fast lookups.
+//!
+//! The entries are stored in two arrays in Eytzinger order, which is
+//! the order of a breadth-first walk of a complete binary search tree:
+//! the root is at index 1 and the children of index `i` are at `2i`
+//! and `2i + 1`. The first levels of the tree, which every lookup
+//! goes through, are next to each other in memory, and a lookup only
+//! needs index arithmetic instead of following pointers. The loop of a
+//! lookup has no branch depending on the keys, so the compiler can use
+//! a conditional move for the comparison.
+//!
+//! Indexes in this module are 1-based, so index 0 means no entry.
+
+use super::tree::{after_start, before_end, Summary, Tree};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::RangeBounds;
+
+/// Immutable map built by `Tree::freeze`.
+#[derive(Clone, Debug)]
+pub struct FrozenTree<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+}
+
+/// Index of the entry after an entry in key order, or 0 if it is the
+/// last entry.
+fn successor(mut i: usize, n: usize) -> usize {
+    if 2 * i < n {
+        // Go to the right child and then to the left as far as
+        // possible.
+        i = 2 * i + 1;
+        while 2 * i <= n {
+            i *= 2;
+        }
+        i
+    } else {
+        // Go up while coming from a right child, and then one more.
+        i >> (i.trailing_ones() + 1)
+    }
+}
+
+/// Index of the first entry in key order, or 0 if there is none.
+fn first(n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
+    let mut i = 1;
+    while 2 * i <= n {
+        i *= 2;
+    }
+    i
+}
+
+impl<K, V> FrozenTree<K, V>
+where
+    K: PartialOrd,
+{
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Index of the first entry for which `right` returns false, or 0
+    /// if it returns true for all entries.
+    ///
+    /// `right` must be true for a prefix of the entries in key order.
+    /// Each step moves to a child depending on the result, and the
+    /// path ends with the ones in the index telling how many times it
+    /// went right after the last time it went left, which is undone by
+    /// the final shift.
+    fn partition_point<F>(&self, right: F) -> usize
+    where
+        F: Fn(&K) -> bool,
+    {
+        let mut i = 1;
+        while i <= self.keys.len() {
+            i = 2 * i + right(&self.keys[i - 1]) as usize;
+        }
+        i >> (i.trailing_ones() + 1)
+    }
+
+    /// Index of the first entry with a key not less than a key.
+    fn lower_bound(&self, key: &K) -> usize {
+        self.partition_point(|other| other < key)
+    }
+
+    fn found(&self, i: usize, key: &K) -> Option<&V> {
+        if i != 0 && self.keys[i - 1] == *key {
+            Some(&self.values[i - 1])
+        } else {
+            None
+        }
+    }
+
+    /// Get a reference to the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.found(self.lower_bound(key), key)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Look up many keys at once, returning the values in the same
+    /// order as the keys.
+    ///
+    /// The keys should be sorted. Each lookup then starts by checking
+    /// the entry found for the previous key and the one after it,
+    /// which avoids a search from the root when the keys are dense.
+    /// Unsorted keys give the same result, only slower.
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<&V>> {
+        let n = self.keys.len();
+        let mut result = Vec::with_capacity(keys.len());
+        let mut previous: Option<(&K, usize)> = None;
+        for key in keys {
+            let i = match previous {
+                // The entry for a larger key is never before the entry
+                // for the previous key.
+                Some((last, i)) if last <= key => {
+                    if i == 0 || self.keys[i - 1] >= *key {
+                        i
+                    } else {
+                        match successor(i, n) {
+                            next if next == 0 || self.keys[next - 1] >= *key => next,
+                            _ => self.lower_bound(key),
+                        }
+                    }
+                }
+                _ => self.lower_bound(key),
+            };
+            result.push(self.found(i, key));
+            previous = Some((key, i));
+        }
+        result
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            tree: self,
+            next: first(self.len()),
+            end: 0,
+        }
+    }
+
+    /// Iterate over the entries with keys in a range, in key order.
+    pub fn range<R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        let next = self.partition_point(|key| !after_start(key, range.start_bound()));
+        let end = self.partition_point(|key| before_end(key, range.end_bound()));
+        let mut iter = Iter {
+            tree: self,
+            next,
+            end,
+        };
+        if next != 0 && end != 0 && self.keys[next - 1] > self.keys[end - 1] {
+            iter.next = 0;
+        }
+        iter
+    }
+}
+
+/// Iterator over the entries of a `FrozenTree` in key order.
+pub struct Iter<'a, K, V> {
+    tree: &'a FrozenTree<K, V>,
+    /// Index of the next entry, or 0 at the end.
+    next: usize,
+    /// Index of the first entry not to return, or 0 to go to the end.
+    end: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let i = self.next;
+        if i == 0 || i == self.end {
+            return None;
+        }
+        self.next = successor(i, self.tree.keys.len());
+        Some((&self.tree.keys[i - 1], &self.tree.values[i - 1]))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a FrozenTree<K, V>
+where
+    K: PartialOrd,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Clone,
+    V: Clone,
+    S: Summary<K, V>,
+{
+    /// Copy the entries into a read-only `FrozenTree`, which is faster
+    /// to search than the tree.
+    pub fn freeze(&self) -> FrozenTree<K, V> {
+        let entries: Vec<_> = self.iter().collect();
+        let n = entries.len();
+
+        // Walk the indexes in key order to find where each entry goes.
+        let mut order = vec![0; n];
+        let mut i = first(n);
+        for rank in 0..n {
+            order[i - 1] = rank;
+            i = successor(i, n);
+        }
+        FrozenTree {
+            keys: order.iter().map(|&rank| entries[rank].0.clone()).collect(),
+            values: order.iter().map(|&rank| entries[rank].1.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::ops::Bound::{Excluded, Included};
+
+    fn frozen(n: i32) -> FrozenTree<i32, i32> {
+        let mut tree = Tree::new();
+        for key in 0..n {
+            tree.insert(key * 2, key * 20);
+        }
+        tree.freeze()
+    }
+
+    #[test]
+    fn test_get() {
+        for n in 0..40 {
+            let tree = frozen(n);
+            assert_eq!(tree.len(), n as usize);
+            for key in -1..2 * n + 1 {
+                let expected = if key >= 0 && key % 2 == 0 && key < 2 * n {
+                    Some(key * 10)
+                } else {
+                    None
+                };
+                assert_eq!(tree.get(&key).cloned(), expected);
+            }
+            let keys: Vec<_> = tree.iter().map(|(k, _)| *k).collect();
+            assert_eq!(keys, (0..n).map(|k| k * 2).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let tree = frozen(50);
+        let keys = |iter: Iter<'_, i32, i32>| iter.map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(10..17)), vec![10, 12, 14, 16]);
+        assert_eq!(keys(tree.range(11..=16)), vec![12, 14, 16]);
+        assert_eq!(keys(tree.range(95..)), vec![96, 98]);
+        assert_eq!(keys(tree.range(..3)), vec![0, 2]);
+        assert_eq!(keys(tree.range(13..13)), Vec::<i32>::new());
+        assert_eq!(
+            keys(tree.range((Included(20), Excluded(10)))),
+            Vec::<i32>::new()
+        );
+        assert_eq!(keys(tree.range(200..)), Vec::<i32>::new());
+        assert_eq!(tree.range(..).count(), 50);
+    }
+
+    #[test]
+    fn test_get_many() {
+        let tree = frozen(100);
+        let keys: Vec<i32> = (-3..210).collect();
+        let expected: Vec<_> = keys.iter().map(|key| tree.get(key)).collect();
+        assert_eq!(tree.get_many(&keys), expected);
+
+        let unsorted = vec![50, 4, 4, 198, 3, 0, 250, 100];
+        let expected: Vec<_> = unsorted.iter().map(|key| tree.get(key)).collect();
+        assert_eq!(tree.get_many(&unsorted), expected);
+        assert_eq!(frozen(0).get_many(&[1, 2]), vec![None, None]);
+    }
+}
@@ -0,0 +1,417 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Container of records with secondary indexes.
+//!
+//! Records are stored in a tree by record id, and each index is a tree
+//! from the key extracted from a record, paired with the record id so
+//! that several records can share a key. Indexes can have different
+//! key types, so they are kept behind a trait object and a typed
+//! `IndexId` is used to look records up through them. Each container
+//! has a unique identity that is stored in its `IndexId`s, so an
+//! `IndexId` of another container is detected rather than used.
+
+use super::tree::{self, Tree};
+use std::any::Any;
+use std::marker::PhantomData;
+use std::ops::Bound;
+use std::result;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Identity of the next container that is created.
+static NEXT_CONTAINER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// A unique index already has a record with the same key. The
+    /// name of the index is given.
+    Duplicate(String),
+    /// There is no record with the given id.
+    NotFound,
+    /// The index belongs to another container.
+    ForeignIndex,
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Identifier of a record in a `MultiIndex`.
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd, Eq, Ord, Hash)]
+pub struct RecordId(u64);
+
+/// Handle to an index of a `MultiIndex` with keys of type `K`.
+pub struct IndexId<K> {
+    container: u64,
+    slot: usize,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K> Clone for IndexId<K> {
+    fn clone(&self) -> IndexId<K> {
+        *self
+    }
+}
+
+impl<K> Copy for IndexId<K> {}
+
+/// Function extracting the key of a record for an index.
+type Extract<T, K> = Box<dyn Fn(&T) -> K + Send + Sync>;
+
+struct Index<T, K> {
+    name: String,
+    unique: bool,
+    extract: Extract<T, K>,
+    entries: Tree<(K, RecordId), ()>,
+}
+
+impl<T, K> Index<T, K>
+where
+    K: PartialOrd + Clone,
+{
+    /// Iterate over the entries with a key.
+    fn range(&self, key: K) -> tree::Iter<'_, (K, RecordId), ()> {
+        let start = (key.clone(), RecordId(0));
+        let end = (key, RecordId(u64::MAX));
+        self.entries
+            .range((Bound::Included(&start), Bound::Included(&end)))
+    }
+}
+
+/// Operations of an index that do not depend on its key type.
+trait AnyIndex<T>: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Check if adding a record would break the uniqueness of the
+    /// index, ignoring the record with the given id.
+    fn conflicts(&self, record: &T, id: RecordId) -> bool;
+
+    fn insert(&mut self, record: &T, id: RecordId);
+
+    fn remove(&mut self, record: &T, id: RecordId);
+
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T, K> AnyIndex<T> for Index<T, K>
+where
+    T: 'static,
+    K: PartialOrd + Clone + Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn conflicts(&self, record: &T, id: RecordId) -> bool {
+        self.unique
+            && self
+                .range((self.extract)(record))
+                .any(|((_, other), _)| *other != id)
+    }
+
+    fn insert(&mut self, record: &T, id: RecordId) {
+        self.entries.insert(((self.extract)(record), id), ());
+    }
+
+    fn remove(&mut self, record: &T, id: RecordId) {
+        self.entries.remove(&((self.extract)(record), id));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Records kept in sync with any number of indexes.
+pub struct MultiIndex<T> {
+    container: u64,
+    records: Tree<RecordId, T>,
+    indexes: Vec<Box<dyn AnyIndex<T>>>,
+    next_id: u64,
+}
+
+impl<T> Default for MultiIndex<T> {
+    fn default() -> MultiIndex<T> {
+        MultiIndex {
+            container: NEXT_CONTAINER.fetch_add(1, Ordering::Relaxed),
+            records: Tree::new(),
+            indexes: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> MultiIndex<T>
+where
+    T: 'static,
+{
+    pub fn new() -> MultiIndex<T> {
+        MultiIndex::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Add an index with keys extracted from the records by a
+    /// function.
+    ///
+    /// The index is built from the records already stored, which fails
+    /// if it is unique and two records have the same key.
+    pub fn add_index<K, F>(&mut self, name: &str, unique: bool, extract: F) -> Result<IndexId<K>>
+    where
+        K: PartialOrd + Clone + Send + Sync + 'static,
+        F: Fn(&T) -> K + Send + Sync + 'static,
+    {
+        let mut index = Index {
+            name: name.to_string(),
+            unique,
+            extract: Box::new(extract),
+            entries: Tree::new(),
+        };
+        for (id, record) in self.records.iter() {
+            if index.conflicts(record, *id) {
+                return Err(Error::Duplicate(index.name));
+            }
+            index.insert(record, *id);
+        }
+        self.indexes.push(Box::new(index));
+        Ok(IndexId {
+            container: self.container,
+            slot: self.indexes.len() - 1,
+            marker: PhantomData,
+        })
+    }
+
+    /// Find the first unique index that a record would conflict with.
+    fn check(&self, record: &T, id: RecordId) -> Result<()> {
+        match self
+            .indexes
+            .iter()
+            .find(|index| index.conflicts(record, id))
+        {
+            Some(index) => Err(Error::Duplicate(index.name().to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a record, returning its id.
+    ///
+    /// Nothing is changed if the record has the same key as another
+    /// record in a unique index.
+    pub fn insert(&mut self, record: T) -> Result<RecordId> {
+        let id = RecordId(self.next_id);
+        self.check(&record, id)?;
+        self.next_id += 1;
+        for index in &mut self.indexes {
+            index.insert(&record, id);
+        }
+        self.records.insert(id, record);
+        Ok(id)
+    }
+
+    /// Replace a record, returning the old record.
+    ///
+    /// Nothing is changed if the new record has the same key as
+    /// another record in a unique index.
+    pub fn update(&mut self, id: RecordId, record: T) -> Result<T> {
+        if self.records.get(&id).is_none() {
+            return Err(Error::NotFound);
+        }
+        self.check(&record, id)?;
+        let old = self.records.remove(&id).unwrap();
+        for index in &mut self.indexes {
+            index.remove(&old, id);
+            index.insert(&record, id);
+        }
+        self.records.insert(id, record);
+        Ok(old)
+    }
+
+    /// Remove a record, returning it if it was present.
+    pub fn remove(&mut self, id: RecordId) -> Option<T> {
+        let record = self.records.remove(&id)?;
+        for index in &mut self.indexes {
+            index.remove(&record, id);
+        }
+        Some(record)
+    }
+
+    pub fn get(&self, id: RecordId) -> Option<&T> {
+        self.records.get(&id)
+    }
+
+    fn index<K>(&self, index: IndexId<K>) -> Result<&Index<T, K>>
+    where
+        K: 'static,
+    {
+        if index.container != self.container {
+            return Err(Error::ForeignIndex);
+        }
+        let index = self.indexes[index.slot].as_any().downcast_ref();
+        Ok(index.expect("index with wrong key type"))
+    }
+
+    /// Iterate over the records with a key in an index, in the order
+    /// they were inserted.
+    ///
+    /// Fails if the index belongs to another container.
+    pub fn find<K>(&self, index: IndexId<K>, key: K) -> Result<Find<'_, T, K>>
+    where
+        K: PartialOrd + Clone + 'static,
+    {
+        Ok(Find {
+            iter: self.index(index)?.range(key),
+            records: &self.records,
+        })
+    }
+
+    /// Get a record with a key in an index, which is the only one if
+    /// the index is unique.
+    ///
+    /// Fails if the index belongs to another container.
+    pub fn find_one<K>(&self, index: IndexId<K>, key: K) -> Result<Option<(RecordId, &T)>>
+    where
+        K: PartialOrd + Clone + 'static,
+    {
+        Ok(self.find(index, key)?.next())
+    }
+
+    /// Iterate over all records by id.
+    pub fn iter(&self) -> tree::Iter<'_, RecordId, T> {
+        self.records.iter()
+    }
+}
+
+/// Iterator over the records with a key in an index.
+pub struct Find<'a, T, K> {
+    iter: tree::Iter<'a, (K, RecordId), ()>,
+    records: &'a Tree<RecordId, T>,
+}
+
+impl<'a, T, K> Iterator for Find<'a, T, K> {
+    type Item = (RecordId, &'a T);
+
+    fn next(&mut self) -> Option<(RecordId, &'a T)> {
+        let ((_, id), _) = self.iter.next()?;
+        let record = self.records.get(id).expect("index out of sync");
+        Some((*id, record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct User {
+        id: u32,
+        name: String,
+        created: u64,
+    }
+
+    fn user(id: u32, name: &str, created: u64) -> User {
+        User {
+            id,
+            name: name.to_string(),
+            created,
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let mut users = MultiIndex::new();
+        let by_id = users.add_index("id", true, |u: &User| u.id).unwrap();
+        let by_name = users.add_index("name", false, |u: &User| u.name.clone());
+        let by_name = by_name.unwrap();
+
+        let alice = users.insert(user(1, "alice", 10)).unwrap();
+        users.insert(user(2, "bob", 20)).unwrap();
+        users.insert(user(3, "alice", 30)).unwrap();
+        assert_eq!(
+            users.insert(user(1, "carol", 40)),
+            Err(Error::Duplicate("id".to_string()))
+        );
+        assert_eq!(users.len(), 3);
+
+        let alices: Vec<_> = users
+            .find(by_name, "alice".to_string())
+            .unwrap()
+            .map(|(_, u)| u.id)
+            .collect();
+        assert_eq!(alices, vec![1, 3]);
+        assert_eq!(users.find_one(by_id, 2).unwrap().unwrap().1.name, "bob");
+        assert_eq!(users.find_one(by_id, 4), Ok(None));
+
+        // Indexes added later see the existing records.
+        let by_created = users.add_index("created", true, |u: &User| u.created);
+        let by_created = by_created.unwrap();
+        assert_eq!(users.find_one(by_created, 30).unwrap().unwrap().1.id, 3);
+        assert_eq!(
+            users
+                .add_index("name", true, |u: &User| u.name.clone())
+                .err(),
+            Some(Error::Duplicate("name".to_string()))
+        );
+
+        assert_eq!(users.remove(alice), Some(user(1, "alice", 10)));
+        assert_eq!(users.find_one(by_id, 1), Ok(None));
+        assert_eq!(users.find(by_name, "alice".to_string()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut users = MultiIndex::new();
+        let by_id = users.add_index("id", true, |u: &User| u.id).unwrap();
+        let first = users.insert(user(1, "alice", 10)).unwrap();
+        users.insert(user(2, "bob", 20)).unwrap();
+
+        // Keeping the same unique key is not a conflict.
+        let old = users.update(first, user(1, "alicia", 10)).unwrap();
+        assert_eq!(old.name, "alice");
+        assert_eq!(
+            users.update(first, user(2, "alicia", 10)),
+            Err(Error::Duplicate("id".to_string()))
+        );
+        assert_eq!(users.get(first).unwrap().id, 1);
+
+        users.update(first, user(5, "alicia", 10)).unwrap();
+        assert_eq!(users.find_one(by_id, 1), Ok(None));
+        assert_eq!(users.find_one(by_id, 5).unwrap().unwrap().0, first);
+        assert_eq!(
+            users.update(RecordId(99), user(9, "x", 0)),
+            Err(Error::NotFound)
+        );
+    }
+
+    #[test]
+    fn test_foreign_index() {
+        let mut users = MultiIndex::new();
+        let by_id = users.add_index("id", true, |u: &User| u.id).unwrap();
+        users.insert(user(1, "alice", 10)).unwrap();
+
+        // The other container has an index in the same slot with
+        // another key type.
+        let mut others = MultiIndex::new();
+        others
+            .add_index("name", true, |u: &User| u.name.clone())
+            .unwrap();
+        others.insert(user(1, "bob", 20)).unwrap();
+        assert_eq!(others.find_one(by_id, 1).err(), Some(Error::ForeignIndex));
+        assert!(others.find(by_id, 1).is_err());
+        assert_eq!(users.find_one(by_id, 1).unwrap().unwrap().1.name, "alice");
+    }
+}
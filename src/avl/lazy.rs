@@ -0,0 +1,391 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! AVL tree with updates to ranges of keys in O(log n).
+//!
+//! An update to a range is applied to the summaries of the O(log n)
+//! subtrees that cover the range and recorded there as pending. The
+//! pending update is pushed down to the entries and child subtrees
+//! only when the node is visited for a change or rotated, so most
+//! entries in the range are never touched by the update itself.
+
+use super::tree::{self, after_start, before_end, Inner, Summary, Tree};
+use std::ops::RangeBounds;
+
+/// Operations needed to fold and update ranges of a tree lazily.
+///
+/// An update applied to a fold of some entries must give the same
+/// result as folding the entries after applying the update to each of
+/// them, and `compose(a, b)` must have the same effect as applying `a`
+/// followed by `b`.
+pub trait Lazy<K, V> {
+    /// Aggregate of the entries in a range, for example a sum.
+    type Fold: Clone;
+
+    /// Change applied to each value in a range, for example adding a
+    /// delta.
+    type Update: Clone;
+
+    /// Fold of a single entry.
+    fn fold(key: &K, value: &V) -> Self::Fold;
+
+    /// Combine the folds of two adjacent ranges.
+    fn combine(left: &Self::Fold, right: &Self::Fold) -> Self::Fold;
+
+    /// Apply an update to a single value.
+    fn apply(update: &Self::Update, value: &mut V);
+
+    /// Apply an update to the fold of a range.
+    fn apply_fold(update: &Self::Update, fold: &mut Self::Fold);
+
+    /// Combine two updates into one that applies `first` and then
+    /// `then`.
+    fn compose(first: &Self::Update, then: &Self::Update) -> Self::Update;
+}
+
+/// Summary holding the fold of a subtree and the update that is
+/// pending for the value and children of the node.
+///
+/// The fold always includes the pending update.
+pub struct Pending<K, V, L: Lazy<K, V>> {
+    fold: L::Fold,
+    pending: Option<L::Update>,
+}
+
+impl<K, V, L: Lazy<K, V>> Pending<K, V, L> {
+    /// Apply an update to the whole subtree.
+    fn add(&mut self, update: &L::Update) {
+        L::apply_fold(update, &mut self.fold);
+        self.pending = Some(match self.pending.take() {
+            None => update.clone(),
+            Some(pending) => L::compose(&pending, update),
+        });
+    }
+}
+
+impl<K, V, L: Lazy<K, V>> Summary<K, V> for Pending<K, V, L> {
+    fn summarize(key: &K, value: &V, left: Option<&Self>, right: Option<&Self>) -> Self {
+        let mut fold = L::fold(key, value);
+        if let Some(left) = left {
+            fold = L::combine(&left.fold, &fold);
+        }
+        if let Some(right) = right {
+            fold = L::combine(&fold, &right.fold);
+        }
+        Pending {
+            fold,
+            pending: None,
+        }
+    }
+
+    fn push_down(&mut self, value: &mut V, left: Option<&mut Self>, right: Option<&mut Self>) {
+        if let Some(update) = self.pending.take() {
+            L::apply(&update, value);
+            if let Some(left) = left {
+                left.add(&update);
+            }
+            if let Some(right) = right {
+                right.add(&update);
+            }
+        }
+    }
+}
+
+type Link<K, V, L> = Option<Inner<K, V, Pending<K, V, L>>>;
+
+/// Apply an update to the entries of a subtree in a range.
+///
+/// The flags tell if all keys of the subtree are known to be after
+/// the start and before the end of the range, which is how the
+/// subtrees that are covered completely are found.
+fn update_node<K, V, L, R>(
+    root: &mut Link<K, V, L>,
+    range: &R,
+    update: &L::Update,
+    after: bool,
+    before: bool,
+) where
+    K: PartialOrd,
+    L: Lazy<K, V>,
+    R: RangeBounds<K>,
+{
+    if let Some(node) = root {
+        if after && before {
+            node.summary.add(update);
+            return;
+        }
+        node.push();
+        let key_after = after_start(&node.key, range.start_bound());
+        let key_before = before_end(&node.key, range.end_bound());
+        if key_after && key_before {
+            L::apply(update, &mut node.value);
+        }
+        if key_after {
+            update_node(&mut node.left, range, update, after, before || key_before);
+        }
+        if key_before {
+            update_node(&mut node.right, range, update, after || key_after, before);
+        }
+        node.update();
+    }
+}
+
+/// Fold the entries of a subtree in a range, with the flags used in
+/// the same way as for `update_node`.
+///
+/// The result includes the updates pending in the subtree, but not
+/// the ones pending further up the tree.
+fn fold_node<K, V, L, R>(
+    root: &Link<K, V, L>,
+    range: &R,
+    after: bool,
+    before: bool,
+) -> Option<L::Fold>
+where
+    K: PartialOrd,
+    L: Lazy<K, V>,
+    R: RangeBounds<K>,
+{
+    let node = root.as_ref()?;
+    if after && before {
+        return Some(node.summary.fold.clone());
+    }
+    let key_after = after_start(&node.key, range.start_bound());
+    let key_before = before_end(&node.key, range.end_bound());
+    let mut fold = None;
+    if key_after {
+        fold = fold_node(&node.left, range, after, before || key_before);
+    }
+    if key_after && key_before {
+        let own = L::fold(&node.key, &node.value);
+        fold = Some(match fold {
+            None => own,
+            Some(left) => L::combine(&left, &own),
+        });
+    }
+    if key_before {
+        if let Some(right) = fold_node(&node.right, range, after || key_after, before) {
+            fold = Some(match fold {
+                None => right,
+                Some(left) => L::combine(&left, &right),
+            });
+        }
+    }
+    if let (Some(fold), Some(pending)) = (fold.as_mut(), node.summary.pending.as_ref()) {
+        L::apply_fold(pending, fold);
+    }
+    fold
+}
+
+/// Push all pending updates in a subtree down to the entries.
+fn flush<K, V, L>(root: &mut Link<K, V, L>)
+where
+    L: Lazy<K, V>,
+{
+    if let Some(node) = root {
+        node.push();
+        flush(&mut node.left);
+        flush(&mut node.right);
+    }
+}
+
+/// AVL tree supporting updates and folds over ranges of keys in
+/// O(log n).
+pub struct LazyTree<K, V, L: Lazy<K, V>> {
+    tree: Tree<K, V, Pending<K, V, L>>,
+}
+
+impl<K, V, L: Lazy<K, V>> Default for LazyTree<K, V, L> {
+    fn default() -> LazyTree<K, V, L> {
+        LazyTree {
+            tree: Tree::default(),
+        }
+    }
+}
+
+impl<K, V, L> LazyTree<K, V, L>
+where
+    K: PartialOrd,
+    L: Lazy<K, V>,
+{
+    pub fn new() -> LazyTree<K, V, L> {
+        LazyTree::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Insert a value under a key, returning the old value if the key
+    /// was present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.tree.insert(key, value)
+    }
+
+    /// Remove a key, returning its value if it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.tree.remove(key)
+    }
+
+    /// Get the value stored under a key.
+    ///
+    /// Updates that are still pending above the entry are applied to
+    /// a copy of the value, so the tree is not changed.
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let mut pending = Vec::new();
+        let mut current = &self.tree.root;
+        while let Some(node) = current {
+            pending.extend(node.summary.pending.as_ref());
+            if *key < node.key {
+                current = &node.left;
+            } else if *key > node.key {
+                current = &node.right;
+            } else {
+                // Updates further down the tree are older, so they
+                // are applied first.
+                let mut value = node.value.clone();
+                for update in pending.iter().rev() {
+                    L::apply(update, &mut value);
+                }
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Apply an update to all values with keys in a range.
+    pub fn update_range<R>(&mut self, range: R, update: L::Update)
+    where
+        R: RangeBounds<K>,
+    {
+        update_node(&mut self.tree.root, &range, &update, false, false);
+    }
+
+    /// Fold all entries with keys in a range, or `None` if the range
+    /// is empty.
+    pub fn fold_range<R>(&self, range: R) -> Option<L::Fold>
+    where
+        R: RangeBounds<K>,
+    {
+        fold_node(&self.tree.root, &range, false, false)
+    }
+
+    /// Iterate over all entries in key order.
+    ///
+    /// This applies all pending updates first, which takes O(n).
+    pub fn iter(&mut self) -> tree::Iter<'_, K, V, Pending<K, V, L>> {
+        flush(&mut self.tree.root);
+        self.tree.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Bound;
+
+    /// Counters with range adds and range sums.
+    struct AddSum;
+
+    impl Lazy<i32, i64> for AddSum {
+        /// Sum and number of entries.
+        type Fold = (i64, i64);
+        type Update = i64;
+
+        fn fold(_key: &i32, value: &i64) -> (i64, i64) {
+            (*value, 1)
+        }
+
+        fn combine(left: &(i64, i64), right: &(i64, i64)) -> (i64, i64) {
+            (left.0 + right.0, left.1 + right.1)
+        }
+
+        fn apply(delta: &i64, value: &mut i64) {
+            *value += delta;
+        }
+
+        fn apply_fold(delta: &i64, fold: &mut (i64, i64)) {
+            fold.0 += delta * fold.1;
+        }
+
+        fn compose(first: &i64, then: &i64) -> i64 {
+            first + then
+        }
+    }
+
+    fn sum(tree: &LazyTree<i32, i64, AddSum>, range: std::ops::Range<i32>) -> i64 {
+        tree.fold_range(range).map_or(0, |(sum, _)| sum)
+    }
+
+    #[test]
+    fn test_update_range() {
+        let mut tree = LazyTree::<_, _, AddSum>::new();
+        let mut expected = vec![0i64; 100];
+        for key in 0..100 {
+            tree.insert(key, 0);
+        }
+        for step in 0..200 {
+            let start = (step * 37) % 100;
+            let end = start + (step * 11) % (100 - start) + 1;
+            let delta = step as i64 - 50;
+            tree.update_range(start..end, delta);
+            for value in &mut expected[start as usize..end as usize] {
+                *value += delta;
+            }
+
+            let (lo, hi) = ((step * 13) % 100, (step * 13) % 100 + 20);
+            let hi = hi.min(100);
+            let want: i64 = expected[lo as usize..hi as usize].iter().sum();
+            assert_eq!(sum(&tree, lo..hi), want);
+            assert_eq!(tree.get(&start), Some(expected[start as usize]));
+        }
+
+        let values: Vec<_> = tree.iter().map(|(_, value)| *value).collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn test_changes_after_update() {
+        let mut tree = LazyTree::<_, _, AddSum>::new();
+        for key in (0..64).map(|key| key * 2) {
+            tree.insert(key, 1);
+        }
+        tree.update_range(.., 10);
+        assert_eq!(sum(&tree, 0..128), 64 * 11);
+
+        // Inserts rotate nodes with pending updates, which must not
+        // apply them to the new entries.
+        for key in (0..64).map(|key| key * 2 + 1) {
+            tree.insert(key, 1);
+        }
+        assert_eq!(sum(&tree, 0..128), 64 * 11 + 64);
+        assert_eq!(tree.get(&10), Some(11));
+        assert_eq!(tree.get(&11), Some(1));
+
+        tree.update_range((Bound::Excluded(10), Bound::Included(20)), 100);
+        assert_eq!(tree.get(&10), Some(11));
+        assert_eq!(tree.get(&11), Some(101));
+        assert_eq!(tree.get(&20), Some(111));
+        assert_eq!(tree.remove(&20), Some(111));
+        assert_eq!(sum(&tree, 10..21), 11 + 5 * 101 + 4 * 111);
+        assert_eq!(tree.fold_range(200..300), None);
+    }
+}
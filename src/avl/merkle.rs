@@ -0,0 +1,306 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Merkle hashes over the AVL tree and diffs between trees.
+//!
+//! Each node stores a hash of the hash of its left child, its key and
+//! value, and the hash of its right child, so the root hash covers all
+//! entries and the shape of the tree. Trees built by the same sequence
+//! of changes have the same root hash.
+//!
+//! The hash is 64-bit FNV-1a over the bytes
+//!
+//! ```text
+//! left      u64    hash of the left child, 0 if there is none
+//! key              encoded using `Codec`
+//! value            encoded using `Codec`
+//! right     u64    hash of the right child, 0 if there is none
+//! ```
+//!
+//! with the hashes stored in little-endian byte order, so it is the
+//! same on all platforms and in all releases.
+
+use super::codec::Codec;
+use super::tree::{height, Inner, Summary, Tree};
+use std::cmp::Ordering;
+use std::io::{self, Write};
+
+const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// Incremental 64-bit FNV-1a hash.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(FNV_OFFSET)
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Write for Fnv1a {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Summary with the hash of a subtree.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Merkle(pub u64);
+
+impl<K: Codec, V: Codec> Summary<K, V> for Merkle {
+    fn summarize(key: &K, value: &V, left: Option<&Merkle>, right: Option<&Merkle>) -> Merkle {
+        let mut hasher = Fnv1a::new();
+        // Writing to the hasher cannot fail.
+        left.map_or(0, |m| m.0).encode(&mut hasher).unwrap();
+        key.encode(&mut hasher).unwrap();
+        value.encode(&mut hasher).unwrap();
+        right.map_or(0, |m| m.0).encode(&mut hasher).unwrap();
+        Merkle(hasher.finish())
+    }
+}
+
+type Link<K, V> = Option<Inner<K, V, Merkle>>;
+
+/// Difference for a key between two trees.
+#[derive(Debug, PartialEq)]
+pub enum Change<'a, K, V> {
+    /// The key is only in the second tree.
+    Added(&'a K, &'a V),
+    /// The key is only in the first tree.
+    Removed(&'a K, &'a V),
+    /// The key is in both trees with different values, given as the
+    /// value in the first tree and then in the second.
+    Changed(&'a K, &'a V, &'a V),
+}
+
+impl<K, V> Tree<K, V, Merkle>
+where
+    K: PartialOrd + Codec,
+    V: Codec,
+{
+    /// Hash of all entries and the shape of the tree.
+    pub fn root_hash(&self) -> u64 {
+        self.summary().map_or(0, |m| m.0)
+    }
+}
+
+/// Find the changes that turn one tree into another, in key order.
+///
+/// Both trees are walked in key order at the same time, and a subtree
+/// is skipped when the next subtree of the other tree has the same
+/// hash. For a tree and a copy of it with a few changes, most subtrees
+/// are the same, so the work depends on the number of changes rather
+/// than the size of the trees. Trees with different shapes are
+/// compared entry by entry.
+pub fn diff<'a, K, V>(a: &'a Tree<K, V, Merkle>, b: &'a Tree<K, V, Merkle>) -> Diff<'a, K, V>
+where
+    K: PartialOrd + Codec,
+    V: PartialEq + Codec,
+{
+    let mut diff = Diff {
+        a: Vec::new(),
+        b: Vec::new(),
+    };
+    push(&mut diff.a, &a.root);
+    push(&mut diff.b, &b.root);
+    diff
+}
+
+/// Subtree or entry that is still to be compared.
+enum Pending<'a, K, V> {
+    Subtree(&'a Link<K, V>),
+    Entry(&'a K, &'a V),
+}
+
+fn push<'a, K, V>(stack: &mut Vec<Pending<'a, K, V>>, link: &'a Link<K, V>) {
+    if link.is_some() {
+        stack.push(Pending::Subtree(link));
+    }
+}
+
+/// Replace the subtree on top of a stack with its left subtree, its
+/// entry and its right subtree, with the left subtree on top.
+fn expand<K, V>(stack: &mut Vec<Pending<'_, K, V>>) {
+    if let Some(Pending::Subtree(Some(node))) = stack.pop() {
+        push(stack, &node.right);
+        stack.push(Pending::Entry(&node.key, &node.value));
+        push(stack, &node.left);
+    }
+}
+
+/// Iterator over the changes between two trees, in key order.
+///
+/// Each tree has a stack with the subtrees and entries that are still
+/// to be compared, with the one with the smallest keys on top.
+pub struct Diff<'a, K, V> {
+    a: Vec<Pending<'a, K, V>>,
+    b: Vec<Pending<'a, K, V>>,
+}
+
+impl<'a, K, V> Iterator for Diff<'a, K, V>
+where
+    K: PartialOrd,
+    V: PartialEq,
+{
+    type Item = Change<'a, K, V>;
+
+    fn next(&mut self) -> Option<Change<'a, K, V>> {
+        loop {
+            match (self.a.last(), self.b.last()) {
+                (None, None) => return None,
+                (Some(&Pending::Entry(key, value)), None) => {
+                    self.a.pop();
+                    return Some(Change::Removed(key, value));
+                }
+                (None, Some(&Pending::Entry(key, value))) => {
+                    self.b.pop();
+                    return Some(Change::Added(key, value));
+                }
+                (Some(&Pending::Entry(a_key, a_value)), Some(&Pending::Entry(b_key, b_value))) => {
+                    match a_key.partial_cmp(b_key) {
+                        Some(Ordering::Less) => {
+                            self.a.pop();
+                            return Some(Change::Removed(a_key, a_value));
+                        }
+                        Some(Ordering::Greater) => {
+                            self.b.pop();
+                            return Some(Change::Added(b_key, b_value));
+                        }
+                        _ => {
+                            self.a.pop();
+                            self.b.pop();
+                            if a_value != b_value {
+                                return Some(Change::Changed(a_key, a_value, b_value));
+                            }
+                        }
+                    }
+                }
+                (Some(Pending::Subtree(a)), Some(Pending::Subtree(b))) => {
+                    let a_hash = a.as_ref().map(|node| node.summary);
+                    let b_hash = b.as_ref().map(|node| node.summary);
+                    if a_hash == b_hash {
+                        self.a.pop();
+                        self.b.pop();
+                    } else if height(a) >= height(b) {
+                        expand(&mut self.a);
+                    } else {
+                        expand(&mut self.b);
+                    }
+                }
+                (Some(Pending::Subtree(_)), _) => expand(&mut self.a),
+                (_, Some(Pending::Subtree(_))) => expand(&mut self.b),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree<I: IntoIterator<Item = (i32, i32)>>(entries: I) -> Tree<i32, i32, Merkle> {
+        let mut tree = Tree::default();
+        for (key, value) in entries {
+            tree.insert(key, value);
+        }
+        tree
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        let mut hasher = Fnv1a::new();
+        assert_eq!(hasher.finish(), 0xCBF2_9CE4_8422_2325);
+        hasher.write_all(b"a").unwrap();
+        assert_eq!(hasher.finish(), 0xAF63_DC4C_8601_EC8C);
+    }
+
+    #[test]
+    fn test_root_hash() {
+        // The hash is specified, so it must never change.
+        assert_eq!(tree(vec![(1, 2)]).root_hash(), 0x1D5B_314F_A2C3_7C26);
+        assert_eq!(Tree::<i32, i32, Merkle>::default().root_hash(), 0);
+
+        let a = tree((0..100).map(|i| (i, i * i)));
+        let b = tree((0..100).map(|i| (i, i * i)));
+        assert_eq!(a.root_hash(), b.root_hash());
+
+        let mut c = tree((0..100).map(|i| (i, i * i)));
+        c.insert(50, 0);
+        assert_ne!(a.root_hash(), c.root_hash());
+        c.insert(50, 2500);
+        assert_eq!(a.root_hash(), c.root_hash());
+        c.remove(&7);
+        assert_ne!(a.root_hash(), c.root_hash());
+    }
+
+    #[test]
+    fn test_hash_covers_shape() {
+        // The same entries in different shapes.
+        let a = tree(vec![(1, 1), (2, 2), (3, 3), (4, 4)]);
+        let b = tree(vec![(2, 2), (1, 1), (3, 3), (4, 4)]);
+        let c = tree(vec![(3, 3), (2, 2), (4, 4), (1, 1)]);
+        assert_eq!(a.root_hash(), b.root_hash());
+        assert_ne!(a.root_hash(), c.root_hash());
+        assert_eq!(diff(&a, &c).count(), 0);
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = tree((0..1000).map(|i| (i, i)));
+        let mut b = tree((0..1000).rev().map(|i| (i, i)));
+        assert_eq!(diff(&a, &b).count(), 0);
+
+        b.remove(&10);
+        b.insert(500, -1);
+        b.insert(2000, 2000);
+        b.insert(-5, -5);
+        let changes: Vec<_> = diff(&a, &b).collect();
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added(&-5, &-5),
+                Change::Removed(&10, &10),
+                Change::Changed(&500, &500, &-1),
+                Change::Added(&2000, &2000),
+            ]
+        );
+
+        let empty = tree(vec![]);
+        assert_eq!(diff(&a, &empty).count(), 1000);
+        assert_eq!(diff(&empty, &a).count(), 1000);
+    }
+
+    #[test]
+    fn test_diff_copy() {
+        let a = tree((0..1000).map(|i| (i, i)));
+        let mut b = tree((0..1000).map(|i| (i, i)));
+        b.insert(250, 0);
+        b.remove(&750);
+        let mut changes = diff(&a, &b);
+        assert_eq!(changes.next(), Some(Change::Changed(&250, &250, &0)));
+        assert_eq!(changes.next(), Some(Change::Removed(&750, &750)));
+        assert_eq!(changes.next(), None);
+    }
+}
@@ -12,8 +12,77 @@
 // implied.  See the License for the specific language governing
 // permissions and limitations under the License.
 
+#[cfg(feature = "std")]
+pub mod bimap;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod codec;
+pub mod frozen;
+#[cfg(feature = "std")]
+pub mod index;
+#[cfg(feature = "std")]
+pub mod lazy;
+#[cfg(feature = "std")]
+pub mod merkle;
+#[cfg(feature = "std")]
+pub mod multiset;
+#[cfg(feature = "std")]
+pub mod mvcc;
+pub mod nearest;
+pub mod observe;
+pub mod page;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod prefix;
+#[cfg(feature = "std")]
+pub mod rope;
+#[cfg(feature = "std")]
+pub mod sequence;
+#[cfg(all(feature = "serde", feature = "std"))]
+mod serialize;
+#[cfg(feature = "std")]
+pub mod set;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
+pub mod store;
+pub mod time;
+#[cfg(feature = "std")]
+pub mod transaction;
 pub mod tree;
 
+#[cfg(feature = "std")]
+pub use bimap::BiMap;
+#[cfg(feature = "std")]
+pub use cache::Cache;
+#[cfg(feature = "std")]
+pub use codec::Codec;
+pub use frozen::FrozenTree;
+#[cfg(feature = "std")]
+pub use index::MultiIndex;
+#[cfg(feature = "std")]
+pub use lazy::{Lazy, LazyTree};
+#[cfg(feature = "std")]
+pub use merkle::{diff, Change, Merkle};
+#[cfg(feature = "std")]
+pub use multiset::MultiSet;
+#[cfg(feature = "std")]
+pub use mvcc::VersionedMap;
+pub use nearest::Distance;
+#[cfg(feature = "std")]
+pub use observe::Batch;
+pub use observe::{Event, ObserverId};
+pub use page::{Cursor, Page};
+pub use prefix::Prefix;
+#[cfg(feature = "std")]
+pub use rope::Rope;
+#[cfg(feature = "std")]
+pub use sequence::Sequence;
+#[cfg(feature = "std")]
+pub use set::AvlSet;
+#[cfg(feature = "std")]
+pub use stats::{QuantileWindow, TreeStats};
+#[cfg(feature = "std")]
+pub use transaction::Transaction;
 pub use tree::*;
-
-
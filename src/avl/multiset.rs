@@ -0,0 +1,209 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Ordered multiset built on the AVL tree.
+//!
+//! Each distinct value is stored once together with the number of
+//! times it occurs, and each subtree keeps the sum of the counts. This
+//! gives order statistics over all occurrences, such as finding the
+//! n-th smallest value, in O(log n).
+
+use super::tree::{self, compare, Summary, Tree, Weight};
+use std::iter::FromIterator;
+
+/// Summary with the total count of the values in a subtree.
+struct Counts(usize);
+
+impl<T> Summary<T, usize> for Counts {
+    fn summarize(
+        _value: &T,
+        count: &usize,
+        left: Option<&Counts>,
+        right: Option<&Counts>,
+    ) -> Counts {
+        Counts(count + left.map_or(0, |c| c.0) + right.map_or(0, |c| c.0))
+    }
+}
+
+impl<T> Weight<T, usize> for Counts {
+    fn weight(_value: &T, count: &usize) -> usize {
+        *count
+    }
+
+    fn total(&self) -> usize {
+        self.0
+    }
+}
+
+pub struct MultiSet<T> {
+    tree: Tree<T, usize, Counts>,
+}
+
+impl<T> Default for MultiSet<T> {
+    fn default() -> MultiSet<T> {
+        MultiSet {
+            tree: Tree::default(),
+        }
+    }
+}
+
+impl<T> MultiSet<T>
+where
+    T: PartialOrd,
+{
+    pub fn new() -> MultiSet<T> {
+        MultiSet::default()
+    }
+
+    /// Number of values in the multiset, counting each occurrence.
+    pub fn len(&self) -> usize {
+        self.tree.total_weight()
+    }
+
+    /// Number of distinct values in the multiset.
+    pub fn distinct_len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Add one occurrence of a value.
+    pub fn insert(&mut self, value: T) {
+        self.insert_many(value, 1);
+    }
+
+    /// Add a number of occurrences of a value.
+    pub fn insert_many(&mut self, value: T, count: usize) {
+        if count > 0 && !self.tree.modify(&value, |c| *c += count) {
+            self.tree.insert(value, count);
+        }
+    }
+
+    /// Remove one occurrence of a value, returning false if the value
+    /// was not present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        match self.tree.get(value) {
+            None => false,
+            Some(1) => self.tree.remove(value).is_some(),
+            Some(_) => self.tree.modify(value, |c| *c -= 1),
+        }
+    }
+
+    /// Remove all occurrences of a value, returning how many there
+    /// were.
+    pub fn remove_all(&mut self, value: &T) -> usize {
+        self.tree.remove(value).unwrap_or(0)
+    }
+
+    /// Number of occurrences of a value.
+    pub fn count(&self, value: &T) -> usize {
+        self.tree.get(value).cloned().unwrap_or(0)
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.tree.get(value).is_some()
+    }
+
+    /// Get the value at an index when all occurrences are listed in
+    /// order, starting from zero.
+    pub fn nth(&self, index: usize) -> Option<&T> {
+        self.tree.select(index).map(|(value, _)| value)
+    }
+
+    /// Number of occurrences of values smaller than a value.
+    pub fn rank(&self, value: &T) -> usize {
+        self.tree.rank(value)
+    }
+
+    /// Iterate over the distinct values and their counts, in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.tree.iter())
+    }
+}
+
+impl<T> FromIterator<T> for MultiSet<T>
+where
+    T: PartialOrd,
+{
+    /// Build a multiset from values in any order by sorting them,
+    /// counting equal values and building the tree in one pass.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> MultiSet<T> {
+        let mut values: Vec<_> = iter.into_iter().collect();
+        values.sort_by(compare);
+        let mut counts: Vec<(T, usize)> = Vec::new();
+        for value in values {
+            match counts.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => counts.push((value, 1)),
+            }
+        }
+        MultiSet {
+            tree: Tree::from_sorted(counts),
+        }
+    }
+}
+
+/// Iterator over the distinct values of a multiset and their counts.
+pub struct Iter<'a, T>(tree::Iter<'a, T, usize, Counts>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<(&'a T, usize)> {
+        self.0.next().map(|(value, count)| (value, *count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts() {
+        let mut set: MultiSet<&str> = vec!["b", "a", "b", "c", "b"].into_iter().collect();
+        assert_eq!(set.len(), 5);
+        assert_eq!(set.distinct_len(), 3);
+        assert_eq!(set.count(&"b"), 3);
+        assert_eq!(set.count(&"d"), 0);
+        assert!(set.remove(&"b"));
+        assert!(set.remove(&"a"));
+        assert!(!set.remove(&"a"));
+        assert!(!set.contains(&"a"));
+        assert_eq!(set.remove_all(&"b"), 2);
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![(&"c", 1)]);
+        set.insert_many("d", 0);
+        assert!(!set.contains(&"d"));
+    }
+
+    #[test]
+    fn test_order_statistics() {
+        let mut set = MultiSet::new();
+        set.insert_many(10, 3);
+        set.insert_many(20, 1);
+        set.insert_many(30, 2);
+        let all: Vec<_> = (0..set.len()).map(|i| *set.nth(i).unwrap()).collect();
+        assert_eq!(all, vec![10, 10, 10, 20, 30, 30]);
+        assert_eq!(set.nth(6), None);
+        assert_eq!(set.rank(&10), 0);
+        assert_eq!(set.rank(&20), 3);
+        assert_eq!(set.rank(&25), 4);
+        assert_eq!(set.rank(&31), 6);
+
+        set.remove(&10);
+        assert_eq!(set.rank(&20), 2);
+        assert_eq!(set.nth(2), Some(&20));
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Multi-version map with reads at a point in time.
+//!
+//! Each key maps to a tree of versions ordered by timestamp, where a
+//! version is either a value or a tombstone marking a delete. A read
+//! at a timestamp finds the latest version at or before that
+//! timestamp, so writes with later timestamps are never visible to
+//! it.
+
+pub use super::time::Timestamp;
+use super::tree::{self, Tree};
+use std::ops::RangeBounds;
+
+/// Versions of a single key. A `None` value is a tombstone.
+type Versions<V> = Tree<Timestamp, Option<V>>;
+
+/// Find the version of a key that is visible at a timestamp.
+fn visible_at<V>(versions: &Versions<V>, ts: Timestamp) -> Option<&V> {
+    versions
+        .range(..=ts)
+        .next_back()
+        .and_then(|(_, value)| value.as_ref())
+}
+
+pub struct VersionedMap<K, V> {
+    keys: Tree<K, Versions<V>>,
+}
+
+impl<K, V> Default for VersionedMap<K, V> {
+    fn default() -> VersionedMap<K, V> {
+        VersionedMap {
+            keys: Tree::default(),
+        }
+    }
+}
+
+impl<K, V> VersionedMap<K, V>
+where
+    K: PartialOrd,
+{
+    pub fn new() -> VersionedMap<K, V> {
+        VersionedMap::default()
+    }
+
+    /// Write a value for a key at a timestamp.
+    ///
+    /// A write at a timestamp that already has a version for the key
+    /// replaces that version.
+    pub fn put(&mut self, key: K, value: V, ts: Timestamp) {
+        self.write(key, Some(value), ts);
+    }
+
+    /// Delete a key at a timestamp.
+    ///
+    /// Reads at or after the timestamp will not see the key until it
+    /// is written again, while reads before it are not affected.
+    pub fn delete(&mut self, key: K, ts: Timestamp) {
+        self.write(key, None, ts);
+    }
+
+    fn write(&mut self, key: K, value: Option<V>, ts: Timestamp) {
+        if let Some(versions) = self.keys.get_mut(&key) {
+            versions.insert(ts, value);
+        } else {
+            let mut versions = Tree::new();
+            versions.insert(ts, value);
+            self.keys.insert(key, versions);
+        }
+    }
+
+    /// Get the value of a key as of a timestamp.
+    pub fn get_at(&self, key: &K, ts: Timestamp) -> Option<&V> {
+        self.keys
+            .get(key)
+            .and_then(|versions| visible_at(versions, ts))
+    }
+
+    /// Iterate over the keys in a range and their values as of a
+    /// timestamp, in key order.
+    ///
+    /// Keys that did not exist at the timestamp are skipped.
+    pub fn range_at<R>(&self, range: R, ts: Timestamp) -> RangeAt<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        RangeAt {
+            iter: self.keys.range(range),
+            ts,
+        }
+    }
+
+    /// Remove versions that are not visible at the watermark or any
+    /// later timestamp.
+    ///
+    /// For each key, the latest version at or before the watermark is
+    /// kept, unless it is a tombstone, together with all versions after
+    /// the watermark. Reads at timestamps before the watermark can give
+    /// wrong results after this.
+    pub fn gc(&mut self, watermark: Timestamp) {
+        self.keys.retain(|_, versions| {
+            let oldest = versions
+                .range(..=watermark)
+                .next_back()
+                .map(|(ts, value)| (*ts, value.is_none()));
+            if let Some((oldest, tombstone)) = oldest {
+                versions.retain(|ts, _| *ts > oldest || (*ts == oldest && !tombstone));
+            }
+            !versions.is_empty()
+        });
+    }
+}
+
+/// Iterator over the entries in a range as of a timestamp.
+pub struct RangeAt<'a, K, V> {
+    iter: tree::Iter<'a, K, Versions<V>>,
+    ts: Timestamp,
+}
+
+impl<'a, K, V> Iterator for RangeAt<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ts = self.ts;
+        self.iter
+            .by_ref()
+            .find_map(|(key, versions)| visible_at(versions, ts).map(|value| (key, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version_count<K: PartialOrd, V>(map: &VersionedMap<K, V>) -> usize {
+        map.keys.iter().map(|(_, versions)| versions.len()).sum()
+    }
+
+    #[test]
+    fn test_get_at() {
+        let mut map = VersionedMap::new();
+        map.put("a", 1, 10);
+        map.put("a", 2, 20);
+        map.delete("a", 30);
+        map.put("a", 4, 40);
+
+        assert_eq!(map.get_at(&"a", 5), None);
+        assert_eq!(map.get_at(&"a", 10), Some(&1));
+        assert_eq!(map.get_at(&"a", 19), Some(&1));
+        assert_eq!(map.get_at(&"a", 20), Some(&2));
+        assert_eq!(map.get_at(&"a", 30), None);
+        assert_eq!(map.get_at(&"a", 45), Some(&4));
+        assert_eq!(map.get_at(&"b", 45), None);
+
+        // A late write with an old timestamp is only visible from
+        // that timestamp until the next version.
+        map.put("a", 3, 25);
+        assert_eq!(map.get_at(&"a", 24), Some(&2));
+        assert_eq!(map.get_at(&"a", 25), Some(&3));
+        assert_eq!(map.get_at(&"a", 30), None);
+    }
+
+    #[test]
+    fn test_range_at() {
+        let mut map = VersionedMap::new();
+        for key in 0..10 {
+            map.put(key, key * 10, key);
+        }
+        map.delete(3, 20);
+        map.put(4, 400, 20);
+
+        let at = |ts| map.range_at(2..6, ts).collect::<Vec<_>>();
+        assert_eq!(at(1), vec![]);
+        assert_eq!(at(3), vec![(&2, &20), (&3, &30)]);
+        assert_eq!(at(10), vec![(&2, &20), (&3, &30), (&4, &40), (&5, &50)]);
+        assert_eq!(at(20), vec![(&2, &20), (&4, &400), (&5, &50)]);
+    }
+
+    #[test]
+    fn test_gc() {
+        let mut map = VersionedMap::new();
+        map.put("a", 1, 10);
+        map.put("a", 2, 20);
+        map.put("a", 3, 30);
+        map.put("b", 1, 10);
+        map.delete("b", 20);
+        map.put("c", 1, 30);
+        assert_eq!(version_count(&map), 6);
+
+        map.gc(25);
+        assert_eq!(version_count(&map), 3);
+        assert_eq!(map.get_at(&"a", 25), Some(&2));
+        assert_eq!(map.get_at(&"a", 30), Some(&3));
+        assert_eq!(map.get_at(&"b", 25), None);
+        assert_eq!(map.get_at(&"c", 30), Some(&1));
+        assert_eq!(map.keys.len(), 2);
+    }
+}
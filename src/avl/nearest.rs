@@ -0,0 +1,250 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Queries by distance for trees with numeric keys.
+//!
+//! The distance between keys must grow with the difference in key
+//! order: for keys `a <= b <= c`, `b` is not further from `a` than
+//! `c` is. The queries rely on this to walk the tree outwards from a
+//! key and stop as soon as the keys are too far.
+
+use super::tree::{Iter, Summary, Tree};
+use core::iter::{Peekable, Rev};
+use core::ops::Bound::{Excluded, Included, Unbounded};
+use core::time::Duration;
+
+/// Distance between two keys.
+pub trait Distance {
+    type Output: PartialOrd;
+
+    fn distance(&self, other: &Self) -> Self::Output;
+}
+
+macro_rules! impl_distance_for_integer {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl Distance for $t {
+                type Output = $u;
+
+                fn distance(&self, other: &$t) -> $u {
+                    self.abs_diff(*other)
+                }
+            }
+        )*
+    };
+}
+
+impl_distance_for_integer!(
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128, usize => usize,
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128, isize => usize
+);
+
+impl Distance for f32 {
+    type Output = f32;
+
+    fn distance(&self, other: &f32) -> f32 {
+        (self - other).abs()
+    }
+}
+
+impl Distance for f64 {
+    type Output = f64;
+
+    fn distance(&self, other: &f64) -> f64 {
+        (self - other).abs()
+    }
+}
+
+impl Distance for Duration {
+    type Output = Duration;
+
+    fn distance(&self, other: &Duration) -> Duration {
+        if self > other {
+            *self - *other
+        } else {
+            *other - *self
+        }
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Distance,
+    S: Summary<K, V>,
+{
+    /// Iterate over the `k` entries with keys closest to a key, from
+    /// the closest to the furthest.
+    ///
+    /// The tree is walked in both directions from the key, so this
+    /// takes O(log n + k). Of two keys at the same distance, the
+    /// smaller one comes first.
+    pub fn nearest<'a>(&'a self, target: &'a K, k: usize) -> Nearest<'a, K, V, S> {
+        Nearest {
+            target,
+            below: self.range((Unbounded, Excluded(target))).rev().peekable(),
+            above: self.range((Included(target), Unbounded)).peekable(),
+            remaining: k,
+        }
+    }
+
+    /// Iterate over the entries with keys at most a distance away from
+    /// a key, in key order.
+    pub fn within<'a>(&'a self, target: &'a K, distance: K::Output) -> Within<'a, K, V, S> {
+        // Find the smallest key in the distance by walking down from
+        // the key, and start from there.
+        let first = self
+            .range((Unbounded, Included(target)))
+            .rev()
+            .take_while(|(key, _)| key.distance(target) <= distance)
+            .last();
+        let iter = match first {
+            Some((key, _)) => self.range((Included(key), Unbounded)),
+            None => self.range((Excluded(target), Unbounded)),
+        };
+        Within {
+            iter,
+            target,
+            distance,
+        }
+    }
+}
+
+/// Iterator over the entries closest to a key, see `Tree::nearest`.
+pub struct Nearest<'a, K, V, S> {
+    target: &'a K,
+    /// Entries before the key, going down.
+    below: Peekable<Rev<Iter<'a, K, V, S>>>,
+    /// Entries from the key, going up.
+    above: Peekable<Iter<'a, K, V, S>>,
+    remaining: usize,
+}
+
+impl<'a, K, V, S> Iterator for Nearest<'a, K, V, S>
+where
+    K: Distance,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let target = self.target;
+        let take_below = match (self.below.peek(), self.above.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some((below, _)), Some((above, _))) => {
+                below.distance(target) <= above.distance(target)
+            }
+        };
+        self.remaining -= 1;
+        if take_below {
+            self.below.next()
+        } else {
+            self.above.next()
+        }
+    }
+}
+
+/// Iterator over the entries within a distance of a key, see
+/// `Tree::within`.
+pub struct Within<'a, K, V, S>
+where
+    K: Distance,
+{
+    iter: Iter<'a, K, V, S>,
+    target: &'a K,
+    distance: K::Output,
+}
+
+impl<'a, K, V, S> Iterator for Within<'a, K, V, S>
+where
+    K: Distance,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let (key, value) = self.iter.next()?;
+        if key.distance(self.target) <= self.distance {
+            Some((key, value))
+        } else {
+            self.iter = Iter::over(None);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys<'a, I: Iterator<Item = (&'a i32, &'a ())>>(iter: I) -> Vec<i32> {
+        iter.map(|(key, _)| *key).collect()
+    }
+
+    #[test]
+    fn test_nearest() {
+        let mut tree = Tree::new();
+        for key in &[1, 4, 10, 11, 15, 30] {
+            tree.insert(*key, ());
+        }
+        assert_eq!(keys(tree.nearest(&12, 3)), vec![11, 10, 15]);
+        assert_eq!(keys(tree.nearest(&10, 2)), vec![10, 11]);
+        // Ties go to the smaller key.
+        assert_eq!(keys(tree.nearest(&7, 2)), vec![4, 10]);
+        assert_eq!(keys(tree.nearest(&-5, 2)), vec![1, 4]);
+        assert_eq!(keys(tree.nearest(&100, 10)), vec![30, 15, 11, 10, 4, 1]);
+        assert_eq!(tree.nearest(&5, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_within() {
+        let mut tree = Tree::new();
+        for key in &[1, 4, 10, 11, 15, 30] {
+            tree.insert(*key, ());
+        }
+        assert_eq!(keys(tree.within(&10, 5)), vec![10, 11, 15]);
+        assert_eq!(keys(tree.within(&8, 4)), vec![4, 10, 11]);
+        assert_eq!(keys(tree.within(&20, 4)), Vec::<i32>::new());
+        assert_eq!(keys(tree.within(&0, 100)), vec![1, 4, 10, 11, 15, 30]);
+    }
+
+    #[test]
+    fn test_other_keys() {
+        let mut prices = Tree::new();
+        for price in &[9.5, 10.25, 10.75, 12.0] {
+            prices.insert(*price, ());
+        }
+        let closest: Vec<f64> = prices.nearest(&10.5, 2).map(|(k, _)| *k).collect();
+        assert_eq!(closest, vec![10.25, 10.75]);
+        assert_eq!(prices.within(&11.0, 1.0).count(), 3);
+
+        let mut times = Tree::new();
+        for ms in &[100, 250, 300, 1000] {
+            times.insert(Duration::from_millis(*ms), ());
+        }
+        let near = Duration::from_millis(280);
+        let count = times.within(&near, Duration::from_millis(50)).count();
+        assert_eq!(count, 2);
+
+        // The distance between unsigned keys does not overflow.
+        let mut bytes = Tree::new();
+        for key in 0..=255u8 {
+            bytes.insert(key, ());
+        }
+        assert_eq!(bytes.within(&250, 10).count(), 16);
+        assert_eq!(bytes.within(&5, 10).count(), 16);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Observers of changes to the AVL tree.
+//!
+//! Observers are registered with `Tree::observe` and called for each
+//! change with the key and values involved. A tree without observers
+//! only pays for checking that the list of observers is empty.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+
+/// Change made to a tree, with borrowed keys and values.
+///
+/// Events are sent before the tree is changed, so they borrow the key
+/// and values from the caller and the tree.
+#[derive(Debug, PartialEq)]
+pub enum Event<'a, K, V> {
+    /// A key that was not present is inserted with a value.
+    Inserted(&'a K, &'a V),
+    /// The value of a key is replaced, given as the old value and then
+    /// the new value.
+    Replaced(&'a K, &'a V, &'a V),
+    /// A key is removed with its value.
+    Removed(&'a K, &'a V),
+    /// The value of a key was changed in place by `Tree::modify`,
+    /// given as the new value. Unlike the other events, this is sent
+    /// after the change.
+    Modified(&'a K, &'a V),
+}
+
+impl<'a, K, V> Event<'a, K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    pub fn to_owned(&self) -> OwnedEvent<K, V> {
+        match *self {
+            Event::Inserted(key, value) => OwnedEvent::Inserted(key.clone(), value.clone()),
+            Event::Replaced(key, old, new) => {
+                OwnedEvent::Replaced(key.clone(), old.clone(), new.clone())
+            }
+            Event::Removed(key, value) => OwnedEvent::Removed(key.clone(), value.clone()),
+            Event::Modified(key, value) => OwnedEvent::Modified(key.clone(), value.clone()),
+        }
+    }
+}
+
+/// Copy of an `Event` that owns its key and values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OwnedEvent<K, V> {
+    Inserted(K, V),
+    Replaced(K, V, V),
+    Removed(K, V),
+    Modified(K, V),
+}
+
+/// Function called with each change made to a tree.
+pub type Observer<K, V> = Box<dyn FnMut(&Event<K, V>) + Send + Sync>;
+
+/// Handle to an observer registered on a tree, used to remove it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObserverId(u64);
+
+/// List of observers registered on a tree.
+pub(crate) struct Observers<K, V> {
+    next_id: u64,
+    list: Vec<(ObserverId, Observer<K, V>)>,
+}
+
+impl<K, V> Default for Observers<K, V> {
+    fn default() -> Observers<K, V> {
+        Observers {
+            next_id: 0,
+            list: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> Observers<K, V> {
+    pub(crate) fn add(&mut self, observer: Observer<K, V>) -> ObserverId {
+        let id = ObserverId(self.next_id);
+        self.next_id += 1;
+        self.list.push((id, observer));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: ObserverId) -> bool {
+        let len = self.list.len();
+        self.list.retain(|(other, _)| *other != id);
+        self.list.len() != len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub(crate) fn notify(&mut self, event: &Event<K, V>) {
+        for (_, observer) in &mut self.list {
+            observer(event);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Observer that keeps owned copies of the events, so they can be
+/// handled together later, for example when a transaction commits.
+///
+/// Clones share the same events, so one clone can be registered on a
+/// tree while another is used to take the events.
+pub struct Batch<K, V> {
+    events: Arc<Mutex<Vec<OwnedEvent<K, V>>>>,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Clone for Batch<K, V> {
+    fn clone(&self) -> Batch<K, V> {
+        Batch {
+            events: Arc::clone(&self.events),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Default for Batch<K, V> {
+    fn default() -> Batch<K, V> {
+        Batch {
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K, V> Batch<K, V>
+where
+    K: Clone + Send + 'static,
+    V: Clone + Send + 'static,
+{
+    pub fn new() -> Batch<K, V> {
+        Batch::default()
+    }
+
+    /// Observer recording the events in this batch.
+    pub fn observer(&self) -> Observer<K, V> {
+        let events = Arc::clone(&self.events);
+        Box::new(move |event| events.lock().unwrap().push(event.to_owned()))
+    }
+
+    /// Number of events recorded and not taken yet.
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Take all recorded events, in the order they happened.
+    pub fn take(&self) -> Vec<OwnedEvent<K, V>> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    /// Drop all recorded events, for example when a transaction is
+    /// rolled back.
+    pub fn clear(&self) {
+        self.events.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::avl::tree::Size;
+    use crate::avl::tree::Tree;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_events() {
+        let mut tree: Tree<i32, &str, Size> = Tree::default();
+        let batch = Batch::new();
+        let id = tree.observe(batch.observer());
+
+        tree.insert(1, "one");
+        tree.insert(2, "two");
+        tree.insert(1, "uno");
+        tree.modify(&2, |value| *value = "dos");
+        assert_eq!(tree.remove(&3), None);
+        tree.remove(&1);
+        tree.insert(3, "three");
+        tree.retain(|key, _| *key != 3);
+        assert_eq!(
+            batch.take(),
+            vec![
+                OwnedEvent::Inserted(1, "one"),
+                OwnedEvent::Inserted(2, "two"),
+                OwnedEvent::Replaced(1, "one", "uno"),
+                OwnedEvent::Modified(2, "dos"),
+                OwnedEvent::Removed(1, "uno"),
+                OwnedEvent::Inserted(3, "three"),
+                OwnedEvent::Removed(3, "three"),
+            ]
+        );
+
+        assert!(tree.unobserve(id));
+        assert!(!tree.unobserve(id));
+        tree.insert(4, "four");
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_borrowed_events() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::clone(&count);
+        let mut tree = Tree::new();
+        tree.observe(move |event| {
+            if let Event::Inserted(key, value) = event {
+                assert_eq!(**key * 10, **value);
+                seen.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        for key in 0..10 {
+            tree.insert(key, key * 10);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 10);
+    }
+}
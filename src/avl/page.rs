@@ -0,0 +1,235 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Pagination over the entries of the AVL tree.
+//!
+//! A page ends with a cursor holding the last key on the page, and the
+//! next page starts after that key. `Tree::page` takes that key
+//! directly, while `Tree::page_after_cursor` takes the cursor. Since the cursor is a key and not
+//! a position, it stays valid when entries are inserted or removed
+//! between pages, and even when the key of the cursor itself is
+//! removed: the next page has the entries with larger keys at the time
+//! it is read, so no entry that is in the tree all along is skipped or
+//! repeated.
+
+use super::tree::{Iter, Summary, Tree, Weight};
+use alloc::vec::Vec;
+use core::iter::Peekable;
+use core::ops::Bound::{self, Excluded, Included, Unbounded};
+
+/// Position to continue paging from, returned with a `Page` and
+/// passed to `Tree::page_after_cursor` to read the next page.
+///
+/// The cursor is opaque, so how the position is kept can change
+/// without changing the interface.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cursor<K>(Bound<K>);
+
+/// Entries of one page, see `Tree::page`.
+#[derive(Debug, PartialEq)]
+pub struct Page<'a, K, V> {
+    pub entries: Vec<(&'a K, &'a V)>,
+    /// Cursor for the next page, or `None` if this is the last page.
+    pub next: Option<Cursor<K>>,
+}
+
+/// Take up to `limit` entries from an iterator over the entries from
+/// `start` as a page.
+///
+/// If entries remain but none were taken, the cursor is at `start`.
+fn take<'a, K, V, S>(
+    mut iter: Peekable<Iter<'a, K, V, S>>,
+    start: Bound<&K>,
+    limit: usize,
+) -> Page<'a, K, V>
+where
+    K: Clone,
+{
+    let entries: Vec<_> = iter.by_ref().take(limit).collect();
+    let next = match (entries.last(), iter.peek()) {
+        (_, None) => None,
+        (Some((key, _)), Some(_)) => Some(Cursor(Excluded((*key).clone()))),
+        (None, Some(_)) => Some(Cursor(start.cloned())),
+    };
+    Page { entries, next }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Clone,
+    S: Summary<K, V>,
+{
+    /// Get up to `limit` entries with keys after `after`, or from the
+    /// start if there is no key, in key order.
+    ///
+    /// This takes O(log n + limit). Passing the last key of a page
+    /// reads the next page, and so does passing the cursor of the page
+    /// to `Tree::page_after_cursor`.
+    pub fn page(&self, after: Option<&K>, limit: usize) -> Page<'_, K, V> {
+        self.page_from(after.map_or(Unbounded, Excluded), limit)
+    }
+
+    /// Get up to `limit` entries after a cursor, or from the start if
+    /// there is no cursor, in key order.
+    ///
+    /// The next page is read by passing the cursor of the page, which
+    /// takes O(log n + limit).
+    pub fn page_after_cursor(&self, after: Option<&Cursor<K>>, limit: usize) -> Page<'_, K, V> {
+        self.page_from(after.map_or(Unbounded, |cursor| cursor.0.as_ref()), limit)
+    }
+
+    fn page_from(&self, start: Bound<&K>, limit: usize) -> Page<'_, K, V> {
+        take(self.range((start, Unbounded)).peekable(), start, limit)
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Clone,
+    S: Weight<K, V>,
+{
+    /// Get up to `limit` entries starting with the entry at a
+    /// position, in key order.
+    ///
+    /// The entry is found with `Tree::select`, so with the `Size`
+    /// summary the position is the index of the entry and this takes
+    /// O(log n + limit). The cursor of the page is used with
+    /// `Tree::page_after_cursor` to read the next pages.
+    pub fn page_at_offset(&self, offset: usize, limit: usize) -> Page<'_, K, V> {
+        match self.select(offset) {
+            Some((key, _)) => {
+                let start = Included(key);
+                take(self.range((start, Unbounded)).peekable(), start, limit)
+            }
+            None => take(Iter::<K, V, S>::over(None).peekable(), Unbounded, limit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avl::tree::Size;
+
+    fn keys(page: &Page<'_, i32, ()>) -> Vec<i32> {
+        page.entries.iter().map(|(key, _)| **key).collect()
+    }
+
+    #[test]
+    fn test_page() {
+        let mut tree = Tree::new();
+        for key in 0..25 {
+            tree.insert(key * 2, ());
+        }
+        let first = tree.page(None, 10);
+        assert_eq!(keys(&first), (0..10).map(|k| k * 2).collect::<Vec<_>>());
+        let cursor = first.next.unwrap();
+        assert_eq!(cursor, Cursor(Excluded(18)));
+
+        let second = tree.page_after_cursor(Some(&cursor), 10);
+        assert_eq!(keys(&second)[0], 20);
+        let third = tree.page_after_cursor(second.next.as_ref(), 10);
+        assert_eq!(keys(&third), vec![40, 42, 44, 46, 48]);
+        assert_eq!(third.next, None);
+
+        // Paging by key gives the same pages.
+        assert_eq!(tree.page(Some(&18), 10), second);
+        assert_eq!(keys(&tree.page(Some(&19), 3)), vec![20, 22, 24]);
+
+        // A full last page has no cursor either.
+        assert_eq!(tree.page(Some(&28), 10).next, None);
+        assert_eq!(tree.page(Some(&48), 10).entries, vec![]);
+        assert_eq!(Tree::<i32, ()>::new().page(None, 0).next, None);
+    }
+
+    #[test]
+    fn test_empty_page() {
+        let mut tree: Tree<i32, (), Size> = Tree::default();
+        for key in 0..5 {
+            tree.insert(key, ());
+        }
+        // An empty page that is not at the end has a cursor for the
+        // same position.
+        let page = tree.page(None, 0);
+        assert_eq!(page.entries, vec![]);
+        assert_eq!(
+            keys(&tree.page_after_cursor(page.next.as_ref(), 2)),
+            vec![0, 1]
+        );
+
+        let page = tree.page(Some(&1), 0);
+        assert_eq!(
+            keys(&tree.page_after_cursor(page.next.as_ref(), 2)),
+            vec![2, 3]
+        );
+        assert_eq!(tree.page(Some(&4), 0).next, None);
+
+        let page = tree.page_at_offset(3, 0);
+        assert_eq!(
+            keys(&tree.page_after_cursor(page.next.as_ref(), 2)),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn test_cursor_across_changes() {
+        let mut tree = Tree::new();
+        for key in 0..100 {
+            tree.insert(key * 10, ());
+        }
+        let mut seen = Vec::new();
+        let mut after: Option<Cursor<i32>> = None;
+        let mut round = 0;
+        loop {
+            let page = tree.page_after_cursor(after.as_ref(), 7);
+            seen.extend(keys(&page));
+            after = page.next;
+            // Remove the key of the cursor and change the tree on both
+            // sides of it.
+            let key = match &after {
+                Some(Cursor(Excluded(key))) => *key,
+                _ => break,
+            };
+            tree.remove(&key);
+            tree.insert(key + 5, ());
+            tree.insert(round, ());
+            tree.remove(&(key + 10));
+            round += 1;
+        }
+        // Every key that stayed in the tree was seen once and in order.
+        let kept: Vec<i32> = (0..100)
+            .map(|k| k * 10)
+            .filter(|k| tree.get(k).is_some())
+            .collect();
+        assert!(kept.iter().all(|k| seen.contains(k)));
+        assert!(seen.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_page_at_offset() {
+        let mut tree: Tree<i32, (), Size> = Tree::default();
+        for key in 0..50 {
+            tree.insert(key * 3, ());
+        }
+        let page = tree.page_at_offset(20, 5);
+        assert_eq!(keys(&page), vec![60, 63, 66, 69, 72]);
+        assert_eq!(page.next, Some(Cursor(Excluded(72))));
+        let next = tree.page_after_cursor(page.next.as_ref(), 5);
+        assert_eq!(keys(&next), vec![75, 78, 81, 84, 87]);
+
+        assert_eq!(keys(&tree.page_at_offset(48, 5)), vec![144, 147]);
+        assert_eq!(tree.page_at_offset(48, 5).next, None);
+        assert_eq!(tree.page_at_offset(50, 5).entries, vec![]);
+    }
+}
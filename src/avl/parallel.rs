@@ -0,0 +1,370 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Bulk operations on the AVL tree running on several threads.
+//!
+//! A tree is split at the root into the left subtree, the root and the
+//! right subtree, which splits the entries at the key of the root. The
+//! subtrees are processed on separate threads, split again until there
+//! is one part for each thread, and the results are joined back with
+//! the entry of the root between them. Each operation gives the same
+//! result as the sequential version, whatever the number of threads.
+
+use super::observe::Event;
+use super::tree::{
+    build_sorted, compare, concat, dedup_sorted, drain_into, join, map_node, Inner, Node, Summary,
+    Tree,
+};
+use std::panic;
+use std::thread;
+
+/// Number of times to split the work in two to get at least one part
+/// for each thread.
+fn depth(threads: usize) -> usize {
+    threads.max(1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// Run two functions, on separate threads unless the work is not to
+/// be split any further.
+fn fork<A, B, FA, FB>(depth: usize, a: FA, b: FB) -> (A, B)
+where
+    A: Send,
+    FA: FnOnce() -> A + Send,
+    FB: FnOnce() -> B,
+{
+    if depth == 0 {
+        return (a(), b());
+    }
+    thread::scope(|scope| {
+        let handle = scope.spawn(a);
+        let b = b();
+        let a = handle
+            .join()
+            .unwrap_or_else(|err| panic::resume_unwind(err));
+        (a, b)
+    })
+}
+
+/// Sort entries by key, keeping entries with the same key in order.
+fn sort<K, V>(mut entries: Vec<(K, V)>, depth: usize) -> Vec<(K, V)>
+where
+    K: PartialOrd + Send,
+    V: Send,
+{
+    if depth == 0 || entries.len() < 2 {
+        entries.sort_by(|a, b| compare(&a.0, &b.0));
+        return entries;
+    }
+    let right = entries.split_off(entries.len() / 2);
+    let (left, right) = fork(
+        depth - 1,
+        || sort(entries, depth - 1),
+        || sort(right, depth - 1),
+    );
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if b.0 < a.0 {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    merged
+}
+
+/// Build a subtree from entries sorted by key, with the same shape as
+/// `build_sorted`.
+fn build<K, V, S>(mut entries: Vec<(K, V)>, depth: usize) -> Option<Inner<K, V, S>>
+where
+    K: Send,
+    V: Send,
+    S: Summary<K, V> + Send,
+{
+    let count = entries.len();
+    if depth == 0 || count < 2 {
+        return build_sorted(&mut entries.into_iter(), count);
+    }
+    let right = entries.split_off(count / 2 + 1);
+    let (key, value) = entries.pop().unwrap();
+    let (left, right) = fork(
+        depth - 1,
+        || build(entries, depth - 1),
+        || build(right, depth - 1),
+    );
+    Some(join(left, Box::new(Node::new(key, value)), right))
+}
+
+/// Filtered subtree with its number of entries and the entries that
+/// were removed, if they are needed.
+type Retained<K, V, S> = (Option<Inner<K, V, S>>, usize, Vec<(K, V)>);
+
+fn retain<K, V, S, F>(
+    root: Option<Inner<K, V, S>>,
+    depth: usize,
+    keep: &F,
+    removed: bool,
+) -> Retained<K, V, S>
+where
+    K: Send,
+    V: Send,
+    S: Summary<K, V> + Send,
+    F: Fn(&K, &mut V) -> bool + Sync,
+{
+    let mut root = match root {
+        None => return (None, 0, Vec::new()),
+        Some(root) if depth > 0 => root,
+        Some(root) => {
+            let mut entries = Vec::new();
+            drain_into(Some(root), &mut entries);
+            let mut kept = Vec::with_capacity(entries.len());
+            let mut dropped = Vec::new();
+            for (key, mut value) in entries {
+                if keep(&key, &mut value) {
+                    kept.push((key, value));
+                } else if removed {
+                    dropped.push((key, value));
+                }
+            }
+            let count = kept.len();
+            return (build_sorted(&mut kept.into_iter(), count), count, dropped);
+        }
+    };
+    root.push();
+    let (left, right) = (root.left.take(), root.right.take());
+    let ((left, left_count, mut dropped), (right, right_count, right_dropped)) = fork(
+        depth - 1,
+        || retain(left, depth - 1, keep, removed),
+        || retain(right, depth - 1, keep, removed),
+    );
+    let kept = keep(&root.key, &mut root.value);
+    let tree = if kept {
+        Some(join(left, root, right))
+    } else {
+        if removed {
+            let Node { key, value, .. } = *root;
+            dropped.push((key, value));
+        }
+        concat(left, right)
+    };
+    dropped.extend(right_dropped);
+    (tree, left_count + kept as usize + right_count, dropped)
+}
+
+fn map_values<K, V, W, S, F>(node: Inner<K, V, S>, depth: usize, f: &F) -> Inner<K, W, S>
+where
+    K: Send,
+    V: Send,
+    W: Send,
+    S: Summary<K, V> + Summary<K, W> + Send,
+    F: Fn(&K, V) -> W + Sync,
+{
+    if depth == 0 {
+        return map_node(*node, &mut |key: &K, value| f(key, value));
+    }
+    let mut node = node;
+    node.push();
+    let Node {
+        key,
+        value,
+        left,
+        right,
+        ..
+    } = *node;
+    let (left, right) = fork(
+        depth - 1,
+        || left.map(|left| map_values(left, depth - 1, f)),
+        || right.map(|right| map_values(right, depth - 1, f)),
+    );
+    let value = f(&key, value);
+    join(left, Box::new(Node::new(key, value)), right)
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Send,
+    V: Send,
+    S: Summary<K, V> + Send,
+{
+    /// Build a tree from entries in any order on a number of threads.
+    ///
+    /// The entries are sorted and the tree is built in parts, giving
+    /// the same tree as collecting the entries into a tree: if several
+    /// entries have the same key, the last one is kept.
+    pub fn par_build(entries: Vec<(K, V)>, threads: usize) -> Tree<K, V, S> {
+        let depth = depth(threads);
+        let mut entries = sort(entries, depth);
+        dedup_sorted(&mut entries);
+        Tree {
+            len: entries.len(),
+            root: build(entries, depth),
+            ..Tree::default()
+        }
+    }
+
+    /// Keep only the entries for which the predicate returns true,
+    /// using a number of threads.
+    ///
+    /// The tree holds the same entries as after `Tree::retain`, but the
+    /// predicate is called from several threads at once and not in key
+    /// order. Observers are told about the removed entries in key
+    /// order once the predicate has been called for all entries.
+    pub fn par_retain<F>(&mut self, threads: usize, keep: F)
+    where
+        F: Fn(&K, &mut V) -> bool + Sync,
+    {
+        let removed = !self.observers.is_empty();
+        let (root, len, dropped) = retain(self.root.take(), depth(threads), &keep, removed);
+        self.root = root;
+        self.len = len;
+        for (key, value) in &dropped {
+            self.observers.notify(&Event::Removed(key, value));
+        }
+    }
+
+    /// Turn the tree into a tree with the same keys and a function
+    /// applied to each value, using a number of threads.
+    ///
+    /// This gives the same tree as `Tree::map_values`, but the function
+    /// is called from several threads at once and not in key order.
+    pub fn par_map_values<W, F>(mut self, threads: usize, f: F) -> Tree<K, W, S>
+    where
+        W: Send,
+        S: Summary<K, W>,
+        F: Fn(&K, V) -> W + Sync,
+    {
+        let depth = depth(threads);
+        Tree {
+            root: self.root.take().map(|root| map_values(root, depth, &f)),
+            len: self.len,
+            ..Tree::default()
+        }
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Sync,
+    V: Sync,
+    S: Summary<K, V> + Sync,
+{
+    /// Look up many keys at once using a number of threads, returning
+    /// the values in the same order as the keys.
+    ///
+    /// The keys are split into one part for each thread, and the parts
+    /// are looked up at the same time.
+    pub fn par_get_many(&self, keys: &[K], threads: usize) -> Vec<Option<&V>> {
+        let threads = threads.max(1);
+        if threads == 1 || keys.len() < 2 {
+            return self.get_many(keys);
+        }
+        let part = keys.len().div_ceil(threads);
+        thread::scope(|scope| {
+            let handles: Vec<_> = keys
+                .chunks(part)
+                .map(|keys| scope.spawn(move || self.get_many(keys)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|err| panic::resume_unwind(err))
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avl::observe::{Batch, OwnedEvent};
+    use crate::avl::tree::Size;
+
+    fn entries(n: u64) -> Vec<(u64, u64)> {
+        (0..n).map(|i| ((i * 7919) % (n / 2 + 1), i)).collect()
+    }
+
+    #[test]
+    fn test_par_build() {
+        let sequential: Tree<u64, u64, Size> = entries(10_000).into_iter().collect();
+        for threads in &[1, 2, 3, 8] {
+            let tree: Tree<u64, u64, Size> = Tree::par_build(entries(10_000), *threads);
+            assert_eq!(tree.len(), sequential.len());
+            assert_eq!(tree.summary(), sequential.summary());
+            assert_eq!(tree.pretty(), sequential.pretty());
+        }
+        let empty: Tree<u64, u64> = Tree::par_build(Vec::new(), 4);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_par_retain() {
+        let keep = |key: &u64, value: &mut u64| {
+            *value += 1;
+            !key.is_multiple_of(3)
+        };
+        let mut sequential: Tree<u64, u64, Size> = entries(5_000).into_iter().collect();
+        sequential.retain(keep);
+        for threads in &[1, 2, 5, 16] {
+            let mut tree: Tree<u64, u64, Size> = entries(5_000).into_iter().collect();
+            tree.par_retain(*threads, keep);
+            assert_eq!(tree.len(), sequential.len());
+            assert_eq!(tree.summary(), sequential.summary());
+            assert!(tree.iter().eq(sequential.iter()));
+            assert!(tree.stats().balance.keys().all(|b| b.abs() <= 1));
+        }
+    }
+
+    #[test]
+    fn test_par_retain_observed() {
+        let mut tree: Tree<u64, u64> = (0..100).map(|i| (i, i)).collect();
+        let batch = Batch::new();
+        tree.observe(batch.observer());
+        tree.par_retain(4, |key, _| !key.is_multiple_of(10));
+        let removed: Vec<_> = (0..100)
+            .step_by(10)
+            .map(|i| OwnedEvent::Removed(i, i))
+            .collect();
+        assert_eq!(batch.take(), removed);
+        assert_eq!(tree.len(), 90);
+    }
+
+    #[test]
+    fn test_par_map_values() {
+        let tree: Tree<u64, u64, Size> = entries(5_000).into_iter().collect();
+        let sequential = tree.map_values(|key, value| key * value);
+        for threads in &[1, 2, 4, 7] {
+            let tree: Tree<u64, u64, Size> = entries(5_000).into_iter().collect();
+            let tree = tree.par_map_values(*threads, |key, value| key * value);
+            assert_eq!(tree.pretty(), sequential.pretty());
+        }
+    }
+
+    #[test]
+    fn test_par_get_many() {
+        let tree: Tree<u64, u64> = entries(1_000).into_iter().collect();
+        let keys: Vec<u64> = (0..700).rev().collect();
+        let expected = tree.get_many(&keys);
+        for threads in &[1, 2, 3, 8, 1000] {
+            assert_eq!(tree.par_get_many(&keys, *threads), expected);
+        }
+        assert!(tree.par_get_many(&[], 4).is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Queries by prefix for trees with string keys.
+//!
+//! The keys starting with a prefix are next to each other in key
+//! order, starting with the prefix itself and ending before the
+//! smallest key that is larger than all of them. That key is found by
+//! dropping the elements at the end of the prefix that cannot be
+//! incremented and incrementing the last of the others, so a prefix
+//! query is a range query.
+
+use super::tree::{Iter, Summary, Tree};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Bound::{Excluded, Included, Unbounded};
+
+/// Keys that can be searched by prefix.
+pub trait Prefix: PartialOrd + Sized {
+    /// Borrowed form of a prefix, such as `str` for `String`.
+    type Slice: ?Sized + ToOwned<Owned = Self>;
+
+    /// Smallest key that is larger than all keys starting with a
+    /// prefix, or `None` if there is none.
+    fn prefix_end(prefix: &Self::Slice) -> Option<Self>;
+}
+
+impl Prefix for Vec<u8> {
+    type Slice = [u8];
+
+    fn prefix_end(prefix: &[u8]) -> Option<Vec<u8>> {
+        let mut end = prefix.to_vec();
+        while let Some(last) = end.pop() {
+            if last < u8::MAX {
+                end.push(last + 1);
+                return Some(end);
+            }
+        }
+        None
+    }
+}
+
+/// Character after a character, skipping the surrogates, which are
+/// not characters.
+fn next_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        ch => core::char::from_u32(ch as u32 + 1),
+    }
+}
+
+impl Prefix for String {
+    type Slice = str;
+
+    /// Strings in UTF-8 are ordered by character, so the last
+    /// character that is not `char::MAX` is incremented.
+    fn prefix_end(prefix: &str) -> Option<String> {
+        let mut end = prefix.to_owned();
+        while let Some(last) = end.pop() {
+            if let Some(next) = next_char(last) {
+                end.push(next);
+                return Some(end);
+            }
+        }
+        None
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: Prefix,
+    S: Summary<K, V>,
+{
+    /// Iterate over the entries with keys starting with a prefix, in
+    /// key order.
+    ///
+    /// Only the part of the tree holding the keys is visited, so this
+    /// takes O(log n + k) for k entries.
+    pub fn prefix_iter(&self, prefix: &K::Slice) -> Iter<'_, K, V, S> {
+        let end = match K::prefix_end(prefix) {
+            Some(end) => Excluded(end),
+            None => Unbounded,
+        };
+        self.range((Included(prefix.to_owned()), end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_prefix_end() {
+        assert_eq!(Vec::prefix_end(&b"ab"[..]), Some(b"ac".to_vec()));
+        assert_eq!(Vec::prefix_end(&b"a\xff\xff"[..]), Some(b"b".to_vec()));
+        assert_eq!(Vec::prefix_end(&b"\xff"[..]), None);
+        assert_eq!(Vec::prefix_end(&b""[..]), None);
+        assert_eq!(String::prefix_end("a/"), Some("a0".to_string()));
+        assert_eq!(
+            String::prefix_end("x\u{D7FF}"),
+            Some("x\u{E000}".to_string())
+        );
+        assert_eq!(String::prefix_end("é\u{10FFFF}"), Some("ê".to_string()));
+        assert_eq!(String::prefix_end("\u{10FFFF}"), None);
+    }
+
+    #[test]
+    fn test_prefix_iter() {
+        let mut tree = Tree::new();
+        let keys = [
+            "service/",
+            "service/host/cpu",
+            "service/host/mem",
+            "service/host0/cpu",
+            "service/hosts",
+            "service/host\u{10FFFF}",
+            "service/hos",
+            "other/host/cpu",
+            "é/a",
+            "é\u{10FFFF}",
+            "ê",
+        ];
+        for key in &keys {
+            tree.insert(key.to_string(), ());
+        }
+        for prefix in &[
+            "service/host/",
+            "service/host",
+            "",
+            "é",
+            "z",
+            "service/host\u{10FFFF}",
+        ] {
+            let found: Vec<_> = tree.prefix_iter(prefix).map(|(k, _)| k.as_str()).collect();
+            let mut expected: Vec<_> = keys
+                .iter()
+                .cloned()
+                .filter(|k| k.starts_with(prefix))
+                .collect();
+            expected.sort_unstable();
+            assert_eq!(found, expected, "prefix {:?}", prefix);
+        }
+    }
+
+    #[test]
+    fn test_prefix_iter_bytes() {
+        let mut tree = Tree::new();
+        for first in &[0u8, 1, 0xfe, 0xff] {
+            for second in &[0u8, 0x7f, 0xff] {
+                tree.insert(vec![*first, *second], ());
+                tree.insert(vec![*first], ());
+            }
+        }
+        let keys = |prefix: &[u8]| -> Vec<Vec<u8>> {
+            tree.prefix_iter(prefix).map(|(k, _)| k.clone()).collect()
+        };
+        assert_eq!(
+            keys(&[0xff]),
+            vec![
+                vec![0xff],
+                vec![0xff, 0],
+                vec![0xff, 0x7f],
+                vec![0xff, 0xff]
+            ]
+        );
+        assert_eq!(keys(&[0xfe, 0xff]), vec![vec![0xfe, 0xff]]);
+        assert_eq!(keys(&[0xff, 0xff, 0xff]), Vec::<Vec<u8>>::new());
+        assert_eq!(keys(&[]).len(), 16);
+    }
+}
@@ -0,0 +1,573 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Rope text buffer built on the AVL tree.
+//!
+//! The text is stored as a sequence of UTF-8 chunks in a tree without
+//! keys, in the same way as `Sequence`. Each chunk keeps the number of
+//! bytes, chars and line breaks in it, and each node caches the sum
+//! over its subtree, so a char offset or a line can be found in
+//! O(log n) and edits only touch the chunks around the edit. When an edit leaves two small chunks next to each
+//! other, they are merged into one.
+
+use super::tree::{build_sorted, concat, join, rebalance, take_smallest, Inner, Node, Summary};
+use std::fmt;
+use std::ops::Range;
+
+/// Largest chunk size in bytes. Chunks can be smaller, for example
+/// after an edit split a chunk.
+const MAX_CHUNK: usize = 1024;
+
+/// Counts of the text in a subtree.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextInfo {
+    pub bytes: usize,
+    pub chars: usize,
+    /// Number of line breaks.
+    pub lines: usize,
+}
+
+impl TextInfo {
+    fn of(text: &str) -> TextInfo {
+        TextInfo {
+            bytes: text.len(),
+            chars: text.chars().count(),
+            lines: text.bytes().filter(|b| *b == b'\n').count(),
+        }
+    }
+
+    fn add(self, other: Option<&TextInfo>) -> TextInfo {
+        match other {
+            None => self,
+            Some(other) => TextInfo {
+                bytes: self.bytes + other.bytes,
+                chars: self.chars + other.chars,
+                lines: self.lines + other.lines,
+            },
+        }
+    }
+
+    fn sub(self, other: TextInfo) -> TextInfo {
+        TextInfo {
+            bytes: self.bytes - other.bytes,
+            chars: self.chars - other.chars,
+            lines: self.lines - other.lines,
+        }
+    }
+}
+
+/// Chunk of text with its counts, which the edits keep up to date so
+/// that the whole chunk is never counted again.
+struct Chunk {
+    text: String,
+    info: TextInfo,
+}
+
+impl Chunk {
+    fn new(text: String) -> Chunk {
+        let info = TextInfo::of(&text);
+        Chunk { text, info }
+    }
+
+    fn insert_str(&mut self, offset: usize, text: &str) {
+        self.text.insert_str(offset, text);
+        self.info = self.info.add(Some(&TextInfo::of(text)));
+    }
+
+    fn split_off(&mut self, offset: usize) -> Chunk {
+        let tail = Chunk::new(self.text.split_off(offset));
+        self.info = self.info.sub(tail.info);
+        tail
+    }
+
+    fn push(&mut self, other: &Chunk) {
+        self.text.push_str(&other.text);
+        self.info = self.info.add(Some(&other.info));
+    }
+}
+
+impl Summary<(), Chunk> for TextInfo {
+    fn summarize(
+        _key: &(),
+        chunk: &Chunk,
+        left: Option<&TextInfo>,
+        right: Option<&TextInfo>,
+    ) -> TextInfo {
+        chunk.info.add(left).add(right)
+    }
+}
+
+type Link = Option<Inner<(), Chunk, TextInfo>>;
+
+fn info(root: &Link) -> TextInfo {
+    root.as_ref()
+        .map_or(TextInfo::default(), |node| node.summary)
+}
+
+/// Byte offset of a char offset in a string.
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Build a balanced subtree from text, split into chunks on char
+/// boundaries.
+fn build(mut text: &str) -> Link {
+    let mut chunks = Vec::new();
+    while !text.is_empty() {
+        let mut end = text.len().min(MAX_CHUNK);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        chunks.push(((), Chunk::new(text[..end].to_string())));
+        text = &text[end..];
+    }
+    let count = chunks.len();
+    build_sorted(&mut chunks.into_iter(), count)
+}
+
+/// Insert text into the chunk that holds a char offset, if it fits in
+/// the chunk, returning false otherwise.
+fn insert_in_chunk(root: &mut Link, index: usize, text: &str) -> bool {
+    match root {
+        None => false,
+        Some(node) => {
+            let left = info(&node.left).chars;
+            let chars = node.value.info.chars;
+            let inserted = if index < left {
+                insert_in_chunk(&mut node.left, index, text)
+            } else if index > left + chars {
+                insert_in_chunk(&mut node.right, index - left - chars, text)
+            } else if node.value.text.len() + text.len() <= MAX_CHUNK {
+                let offset = byte_offset(&node.value.text, index - left);
+                node.value.insert_str(offset, text);
+                true
+            } else {
+                false
+            };
+            if inserted {
+                node.update();
+            }
+            inserted
+        }
+    }
+}
+
+/// Split a subtree into the text before a char offset and the text
+/// from the offset on, splitting a chunk if needed.
+fn split_at(root: Link, index: usize) -> (Link, Link) {
+    match root {
+        None => (None, None),
+        Some(mut node) => {
+            let chars = node.value.info.chars;
+            let left = node.left.take();
+            let right = node.right.take();
+            let before = info(&left).chars;
+            if index <= before {
+                let (first, rest) = split_at(left, index);
+                (first, Some(join(rest, node, right)))
+            } else if index >= before + chars {
+                let (first, rest) = split_at(right, index - before - chars);
+                (Some(join(left, node, first)), rest)
+            } else {
+                let offset = byte_offset(&node.value.text, index - before);
+                let tail = node.value.split_off(offset);
+                let tail = Box::new(Node::new((), tail));
+                (Some(join(left, node, None)), Some(join(None, tail, right)))
+            }
+        }
+    }
+}
+
+/// Remove the last node of a subtree, returning the rest of the
+/// subtree and the node.
+fn take_last(mut root: Inner<(), Chunk, TextInfo>) -> (Link, Inner<(), Chunk, TextInfo>) {
+    match root.right.take() {
+        Some(right) => {
+            let (rest, node) = take_last(right);
+            root.right = rest;
+            root.update();
+            (Some(rebalance(root)), node)
+        }
+        None => (root.left.take(), root),
+    }
+}
+
+/// Concatenate two subtrees, merging the last chunk of the first with
+/// the first chunk of the second if they fit in one chunk.
+fn merge(left: Link, right: Link) -> Link {
+    let mut last = left.as_deref();
+    while let Some(next) = last.and_then(|node| node.right.as_deref()) {
+        last = Some(next);
+    }
+    let mut first = right.as_deref();
+    while let Some(next) = first.and_then(|node| node.left.as_deref()) {
+        first = Some(next);
+    }
+    match (last, first) {
+        (Some(last), Some(first))
+            if last.value.text.len() + first.value.text.len() <= MAX_CHUNK => {}
+        _ => return concat(left, right),
+    }
+    let (left, mut last) = take_last(left.unwrap());
+    let (right, first) = take_smallest(right.unwrap());
+    last.value.push(&first.value);
+    Some(join(left, last, right))
+}
+
+/// Find the char offset just after the line break with a given
+/// number, counting from one.
+fn after_line_break(root: &Link, mut line: usize) -> usize {
+    let mut current = root;
+    let mut offset = 0;
+    while let Some(node) = current {
+        let left = info(&node.left);
+        let own = node.value.info;
+        if line <= left.lines {
+            current = &node.left;
+        } else if line > left.lines + own.lines {
+            line -= left.lines + own.lines;
+            offset += left.chars + own.chars;
+            current = &node.right;
+        } else {
+            let (end, _) = node
+                .value
+                .text
+                .match_indices('\n')
+                .nth(line - left.lines - 1)
+                .unwrap();
+            return offset + left.chars + node.value.text[..=end].chars().count();
+        }
+    }
+    offset
+}
+
+/// Text buffer with O(log n) edits at any position.
+///
+/// Positions are char offsets, so a position is never inside the
+/// encoding of a char.
+#[derive(Default)]
+pub struct Rope {
+    root: Link,
+}
+
+impl Rope {
+    pub fn new() -> Rope {
+        Rope::default()
+    }
+
+    /// Length of the text in bytes.
+    pub fn len_bytes(&self) -> usize {
+        info(&self.root).bytes
+    }
+
+    /// Length of the text in chars.
+    pub fn len_chars(&self) -> usize {
+        info(&self.root).chars
+    }
+
+    /// Number of lines in the text, which is one more than the number
+    /// of line breaks.
+    pub fn len_lines(&self) -> usize {
+        info(&self.root).lines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Insert text at a char offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offset is after the end of the text.
+    pub fn insert(&mut self, index: usize, text: &str) {
+        assert!(index <= self.len_chars(), "insertion index out of bounds");
+        if text.is_empty() || insert_in_chunk(&mut self.root, index, text) {
+            return;
+        }
+        let (first, rest) = split_at(self.root.take(), index);
+        self.root = merge(merge(first, build(text)), rest);
+    }
+
+    /// Remove the text in a range of char offsets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is decreasing or ends after the end of the
+    /// text.
+    pub fn remove(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end, "decreasing range");
+        assert!(range.end <= self.len_chars(), "range out of bounds");
+        let (rest, after) = split_at(self.root.take(), range.end);
+        let (before, _) = split_at(rest, range.start);
+        self.root = merge(before, after);
+    }
+
+    /// Char offset of the start of a line, counting from zero, or
+    /// `None` if there is no such line.
+    pub fn line_to_char(&self, line: usize) -> Option<usize> {
+        if line >= self.len_lines() {
+            None
+        } else if line == 0 {
+            Some(0)
+        } else {
+            Some(after_line_break(&self.root, line))
+        }
+    }
+
+    /// Line of a char offset, counting from zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offset is after the end of the text.
+    pub fn char_to_line(&self, index: usize) -> usize {
+        assert!(index <= self.len_chars(), "index out of bounds");
+        let mut current = &self.root;
+        let mut index = index;
+        let mut line = 0;
+        while let Some(node) = current {
+            let left = info(&node.left);
+            let own = node.value.info;
+            if index < left.chars {
+                current = &node.left;
+            } else if index >= left.chars + own.chars {
+                index -= left.chars + own.chars;
+                line += left.lines + own.lines;
+                current = &node.right;
+            } else {
+                let offset = byte_offset(&node.value.text, index - left.chars);
+                let breaks = node.value.text[..offset].bytes().filter(|b| *b == b'\n');
+                return line + left.lines + breaks.count();
+            }
+        }
+        line
+    }
+
+    /// Iterate over the chunks of the text.
+    pub fn chunks(&self) -> Chunks<'_> {
+        self.slice(0..self.len_chars())
+    }
+
+    /// Iterate over the text in a range of char offsets, as a series
+    /// of string slices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is decreasing or ends after the end of the
+    /// text.
+    pub fn slice(&self, range: Range<usize>) -> Chunks<'_> {
+        assert!(range.start <= range.end, "decreasing range");
+        assert!(range.end <= self.len_chars(), "range out of bounds");
+        let mut stack = Vec::new();
+        let mut skip = range.start;
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            let left = info(&node.left).chars;
+            let chars = node.value.info.chars;
+            if skip < left {
+                stack.push(node);
+                current = node.left.as_deref();
+            } else if skip < left + chars {
+                stack.push(node);
+                skip -= left;
+                break;
+            } else {
+                skip -= left + chars;
+                current = node.right.as_deref();
+            }
+        }
+        Chunks {
+            stack,
+            skip,
+            remaining: range.end - range.start,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Rope {
+    fn from(text: &'a str) -> Rope {
+        Rope { root: build(text) }
+    }
+}
+
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over a range of the text of a rope as string slices.
+pub struct Chunks<'a> {
+    /// Nodes whose chunks are still to be visited, with the next one
+    /// on top.
+    stack: Vec<&'a Node<(), Chunk, TextInfo>>,
+    /// Chars to skip at the start of the next chunk.
+    skip: usize,
+    /// Chars left in the range.
+    remaining: usize,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        while self.remaining > 0 {
+            let node = self.stack.pop()?;
+            let mut current = node.right.as_deref();
+            while let Some(next) = current {
+                self.stack.push(next);
+                current = next.left.as_deref();
+            }
+
+            let text = &node.value.text;
+            let chunk = &text[byte_offset(text, self.skip)..];
+            self.skip = 0;
+            let end = byte_offset(chunk, self.remaining);
+            let chunk = &chunk[..end];
+            self.remaining -= chunk.chars().count();
+            if !chunk.is_empty() {
+                return Some(chunk);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avl::tree::height;
+
+    fn is_balanced(root: &Link) -> bool {
+        match root {
+            None => true,
+            Some(node) => {
+                let balance = height(&node.left) - height(&node.right);
+                balance.abs() <= 1 && is_balanced(&node.left) && is_balanced(&node.right)
+            }
+        }
+    }
+
+    /// Check that the counts kept with each chunk are the counts of its
+    /// text.
+    fn counts_match(root: &Link) -> bool {
+        match root {
+            None => true,
+            Some(node) => {
+                node.value.info == TextInfo::of(&node.value.text)
+                    && counts_match(&node.left)
+                    && counts_match(&node.right)
+            }
+        }
+    }
+
+    #[test]
+    fn test_edit() {
+        let mut rope = Rope::new();
+        let mut expected = String::new();
+        for i in 0..2000 {
+            let chars = expected.chars().count();
+            let index = (i * 7919) % (chars + 1);
+            let text = if i % 3 == 0 { "é\n" } else { "ab" };
+            rope.insert(index, text);
+            expected.insert_str(byte_offset(&expected, index), text);
+        }
+        assert_eq!(rope.to_string(), expected);
+        assert_eq!(rope.len_bytes(), expected.len());
+        assert_eq!(rope.len_chars(), expected.chars().count());
+        assert!(is_balanced(&rope.root));
+
+        for i in 0..500 {
+            let chars = expected.chars().count();
+            let start = (i * 31) % chars;
+            let end = (start + 7).min(chars);
+            rope.remove(start..end);
+            let range = byte_offset(&expected, start)..byte_offset(&expected, end);
+            expected.replace_range(range, "");
+        }
+        assert_eq!(rope.to_string(), expected);
+        assert!(is_balanced(&rope.root));
+        assert!(counts_match(&rope.root));
+    }
+
+    #[test]
+    fn test_large_insert() {
+        let text = "line\n".repeat(1000);
+        let mut rope = Rope::from("[]");
+        rope.insert(1, &text);
+        assert_eq!(rope.to_string(), format!("[{}]", text));
+        assert!(rope.chunks().count() > 1);
+        assert!(is_balanced(&rope.root));
+    }
+
+    #[test]
+    fn test_lines() {
+        let text = "first\nsecond\n\nfourth ü\nlast".repeat(100);
+        let rope = Rope::from(text.as_str());
+        let lines: Vec<_> = text.split('\n').collect();
+        assert_eq!(rope.len_lines(), lines.len());
+
+        let mut offset = 0;
+        for (number, line) in lines.iter().enumerate() {
+            assert_eq!(rope.line_to_char(number), Some(offset));
+            assert_eq!(rope.char_to_line(offset), number);
+            offset += line.chars().count() + 1;
+        }
+        assert_eq!(rope.line_to_char(lines.len()), None);
+        assert_eq!(rope.char_to_line(rope.len_chars()), lines.len() - 1);
+    }
+
+    #[test]
+    fn test_slice() {
+        let text: String = (0..3000)
+            .map(|i| if i % 2 == 0 { 'x' } else { 'ß' })
+            .collect();
+        let rope = Rope::from(text.as_str());
+        let chars: Vec<char> = text.chars().collect();
+        for &(start, end) in &[(0, 0), (0, 3000), (5, 17), (1000, 2500), (2999, 3000)] {
+            let slice: String = rope.slice(start..end).collect();
+            let expected: String = chars[start..end].iter().collect();
+            assert_eq!(slice, expected);
+        }
+    }
+
+    #[test]
+    fn test_merge_chunks() {
+        let mut rope = Rope::from("x".repeat(3 * MAX_CHUNK).as_str());
+        assert_eq!(rope.chunks().count(), 3);
+        // Each removal splits a chunk, and the pieces are merged again.
+        for i in 0..500 {
+            let start = (i * 7919) % rope.len_chars();
+            rope.remove(start..start + 1);
+        }
+        assert_eq!(rope.chunks().count(), 3);
+        assert_eq!(rope.len_chars(), 3 * MAX_CHUNK - 500);
+        assert!(counts_match(&rope.root));
+
+        // Inserting into a full chunk merges the new text with the text
+        // before it.
+        let mut rope = Rope::from("x".repeat(MAX_CHUNK).as_str());
+        rope.insert(10, "ab");
+        assert_eq!(
+            rope.chunks().map(str::len).collect::<Vec<_>>(),
+            vec![12, MAX_CHUNK - 10]
+        );
+        assert!(is_balanced(&rope.root));
+    }
+}
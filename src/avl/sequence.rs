@@ -0,0 +1,336 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Indexable list built on the AVL tree.
+//!
+//! The nodes of a sequence have no keys. Instead, the position of a
+//! node is given by the number of nodes before it, which is found from
+//! the subtree sizes kept in the `Size` summary. Inserting, removing,
+//! splitting and concatenating at any position take O(log n).
+
+use super::tree::{self, concat, join, rebalance, take_smallest, total, Inner, Node, Size};
+use std::iter::FromIterator;
+use std::ops::{Index, IndexMut};
+
+type Link<T> = Option<Inner<(), T, Size>>;
+
+/// Insert a value at a position of a subtree.
+fn insert_at<T>(root: Link<T>, index: usize, value: T) -> Inner<(), T, Size> {
+    match root {
+        None => Box::new(Node::new((), value)),
+        Some(mut node) => {
+            let left = total(&node.left);
+            if index <= left {
+                node.left = Some(insert_at(node.left.take(), index, value));
+            } else {
+                node.right = Some(insert_at(node.right.take(), index - left - 1, value));
+            }
+            node.update();
+            rebalance(node)
+        }
+    }
+}
+
+/// Remove the value at a position of a subtree, which must be in
+/// bounds.
+fn remove_at<T>(mut node: Inner<(), T, Size>, index: usize) -> (Link<T>, T) {
+    let left = total(&node.left);
+    if index < left {
+        let (new_left, value) = remove_at(node.left.take().unwrap(), index);
+        node.left = new_left;
+        node.update();
+        (Some(rebalance(node)), value)
+    } else if index > left {
+        let (new_right, value) = remove_at(node.right.take().unwrap(), index - left - 1);
+        node.right = new_right;
+        node.update();
+        (Some(rebalance(node)), value)
+    } else {
+        let Node {
+            value, left, right, ..
+        } = *node;
+        match right {
+            None => (left, value),
+            Some(right) => {
+                let (right, middle) = take_smallest(right);
+                (Some(join(left, middle, right)), value)
+            }
+        }
+    }
+}
+
+/// Split a subtree into the first `index` values and the rest.
+fn split_at<T>(root: Link<T>, index: usize) -> (Link<T>, Link<T>) {
+    match root {
+        None => (None, None),
+        Some(mut node) => {
+            let left = node.left.take();
+            let right = node.right.take();
+            let size = total(&left);
+            if index <= size {
+                let (first, rest) = split_at(left, index);
+                (first, Some(join(rest, node, right)))
+            } else {
+                let (first, rest) = split_at(right, index - size - 1);
+                (Some(join(left, node, first)), rest)
+            }
+        }
+    }
+}
+
+/// Find the node at a position of a subtree.
+fn node_at<T>(root: &Link<T>, mut index: usize) -> Option<&Node<(), T, Size>> {
+    let mut current = root;
+    while let Some(node) = current {
+        let left = total(&node.left);
+        if index < left {
+            current = &node.left;
+        } else if index > left {
+            index -= left + 1;
+            current = &node.right;
+        } else {
+            return Some(node);
+        }
+    }
+    None
+}
+
+/// List of values supporting O(log n) access and changes at any
+/// position.
+pub struct Sequence<T> {
+    root: Link<T>,
+}
+
+impl<T> Default for Sequence<T> {
+    fn default() -> Sequence<T> {
+        Sequence { root: None }
+    }
+}
+
+impl<T> Sequence<T> {
+    pub fn new() -> Sequence<T> {
+        Sequence::default()
+    }
+
+    pub fn len(&self) -> usize {
+        total(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    pub fn height(&self) -> isize {
+        tree::height(&self.root)
+    }
+
+    /// Get a reference to the value at a position.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        node_at(&self.root, index).map(|node| &node.value)
+    }
+
+    /// Get a mutable reference to the value at a position.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        // Changing a value does not change the sizes, so no summary
+        // needs to be recomputed.
+        let mut current = &mut self.root;
+        let mut index = index;
+        while let Some(node) = current {
+            let left = total(&node.left);
+            if index < left {
+                current = &mut node.left;
+            } else if index > left {
+                index -= left + 1;
+                current = &mut node.right;
+            } else {
+                return Some(&mut node.value);
+            }
+        }
+        None
+    }
+
+    /// Insert a value at a position, shifting all values after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "insertion index out of bounds");
+        self.root = Some(insert_at(self.root.take(), index, value));
+    }
+
+    /// Add a value at the end of the sequence.
+    pub fn push(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Remove and return the value at a position, or `None` if the
+    /// position is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let (root, value) = remove_at(self.root.take().unwrap(), index);
+        self.root = root;
+        Some(value)
+    }
+
+    /// Split the sequence in two at a position, returning the values
+    /// from the position on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn split_off(&mut self, index: usize) -> Sequence<T> {
+        assert!(index <= self.len(), "split index out of bounds");
+        let (first, rest) = split_at(self.root.take(), index);
+        self.root = first;
+        Sequence { root: rest }
+    }
+
+    /// Move all values of another sequence to the end of this one.
+    pub fn append(&mut self, other: &mut Sequence<T>) {
+        self.root = concat(self.root.take(), other.root.take());
+    }
+
+    /// Iterate over the values in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(tree::Iter::over(self.root.as_deref()))
+    }
+}
+
+impl<T> Index<usize> for Sequence<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for Sequence<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T> FromIterator<T> for Sequence<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Sequence<T> {
+        let mut sequence = Sequence::new();
+        for value in iter {
+            sequence.push(value);
+        }
+        sequence
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Sequence<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over the values of a sequence in order.
+pub struct Iter<'a, T>(tree::Iter<'a, (), T, Size>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|(_, value)| value)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back().map(|(_, value)| value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_balanced<T>(root: &Link<T>) -> bool {
+        match root {
+            None => true,
+            Some(node) => {
+                let balance = tree::height(&node.left) - tree::height(&node.right);
+                balance.abs() <= 1 && is_balanced(&node.left) && is_balanced(&node.right)
+            }
+        }
+    }
+
+    fn values(sequence: &Sequence<i32>) -> Vec<i32> {
+        sequence.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut sequence = Sequence::new();
+        let mut expected = Vec::new();
+        for i in 0..200 {
+            let index = (i * 7919) % (expected.len() + 1);
+            sequence.insert(index, i as i32);
+            expected.insert(index, i as i32);
+        }
+        assert!(is_balanced(&sequence.root));
+        assert_eq!(values(&sequence), expected);
+        assert_eq!(sequence[17], expected[17]);
+
+        for step in 0..200 {
+            let index = (step * 13) % expected.len();
+            assert_eq!(sequence.remove(index), Some(expected.remove(index)));
+            assert_eq!(sequence.len(), expected.len());
+        }
+        assert!(is_balanced(&sequence.root));
+        assert!(sequence.is_empty());
+        assert_eq!(sequence.remove(0), None);
+    }
+
+    #[test]
+    fn test_index_mut() {
+        let mut sequence: Sequence<i32> = (0..5).collect();
+        sequence[2] = 20;
+        *sequence.get_mut(4).unwrap() += 1;
+        assert_eq!(values(&sequence), vec![0, 1, 20, 3, 5]);
+        assert_eq!(sequence.get(5), None);
+        assert_eq!(sequence.iter().next_back(), Some(&5));
+    }
+
+    #[test]
+    fn test_split_append() {
+        for index in 0..=50 {
+            let mut first: Sequence<i32> = (0..50).collect();
+            let mut rest = first.split_off(index);
+            assert!(is_balanced(&first.root) && is_balanced(&rest.root));
+            assert_eq!(values(&first), (0..index as i32).collect::<Vec<_>>());
+            assert_eq!(values(&rest), (index as i32..50).collect::<Vec<_>>());
+
+            first.append(&mut rest);
+            assert!(is_balanced(&first.root));
+            assert!(rest.is_empty());
+            assert_eq!(values(&first), (0..50).collect::<Vec<_>>());
+        }
+
+        let mut small: Sequence<i32> = (0..3).collect();
+        let mut large: Sequence<i32> = (3..300).collect();
+        small.append(&mut large);
+        assert!(is_balanced(&small.root));
+        assert_eq!(values(&small), (0..300).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Serde support for trees, enabled with the `serde` feature.
+//!
+//! Trees are serialized as maps with the entries in key order. When
+//! deserializing, entries that arrive in sorted order are built into
+//! a tree in linear time, otherwise they are inserted one by one.
+
+use super::tree::{Summary, Tree};
+use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::marker::PhantomData;
+
+impl<K, V, S> Serialize for Tree<K, V, S>
+where
+    K: Serialize + PartialOrd,
+    V: Serialize,
+    S: Summary<K, V>,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (key, value) in self.iter() {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
+
+struct TreeVisitor<K, V, S> {
+    marker: PhantomData<Tree<K, V, S>>,
+}
+
+impl<'de, K, V, S> Visitor<'de> for TreeVisitor<K, V, S>
+where
+    K: Deserialize<'de> + PartialOrd,
+    V: Deserialize<'de>,
+    S: Summary<K, V>,
+{
+    type Value = Tree<K, V, S>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut access: M) -> Result<Self::Value, M::Error> {
+        let mut entries: Vec<(K, V)> = Vec::new();
+        let mut sorted = true;
+        while let Some((key, value)) = access.next_entry::<K, V>()? {
+            if let Some((last, _)) = entries.last() {
+                sorted = sorted && key.partial_cmp(last) == Some(Ordering::Greater);
+            }
+            entries.push((key, value));
+        }
+
+        if sorted {
+            Ok(Tree::from_sorted(entries))
+        } else {
+            let mut tree = Tree::default();
+            for (key, value) in entries {
+                tree.insert(key, value);
+            }
+            Ok(tree)
+        }
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for Tree<K, V, S>
+where
+    K: Deserialize<'de> + PartialOrd,
+    V: Deserialize<'de>,
+    S: Summary<K, V>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(TreeVisitor {
+            marker: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut tree = Tree::new();
+        for i in 0..20 {
+            tree.insert(format!("key{:02}", (i * 7) % 20), i);
+        }
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(json.starts_with(r#"{"key00":0,"key01":3,"#));
+        let copy: Tree<String, i32> = serde_json::from_str(&json).unwrap();
+        assert!(copy.iter().eq(tree.iter()));
+
+        let copy: Tree<String, i32> = serde_json::from_str(r#"{"b":2,"a":1,"c":3}"#).unwrap();
+        let keys: Vec<_> = copy.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, vec!["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Ordered set built on the AVL tree.
+
+use super::tree::{self, Tree};
+use std::iter::{FromIterator, FusedIterator, Peekable};
+use std::ops::RangeBounds;
+
+pub struct AvlSet<T> {
+    tree: Tree<T, ()>,
+}
+
+impl<T> Default for AvlSet<T> {
+    fn default() -> AvlSet<T> {
+        AvlSet {
+            tree: Tree::default(),
+        }
+    }
+}
+
+impl<T> AvlSet<T>
+where
+    T: PartialOrd,
+{
+    pub fn new() -> AvlSet<T> {
+        AvlSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Add a value to the set, returning false if it was already
+    /// present.
+    pub fn insert(&mut self, value: T) -> bool {
+        self.tree.insert(value, ()).is_none()
+    }
+
+    /// Remove a value from the set, returning false if it was not
+    /// present.
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.tree.remove(value).is_some()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.tree.get(value).is_some()
+    }
+
+    /// Iterate over the values in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.tree.iter())
+    }
+
+    /// Iterate over the values in a range, in order.
+    pub fn range<R>(&self, range: R) -> Iter<'_, T>
+    where
+        R: RangeBounds<T>,
+    {
+        Iter(self.tree.range(range))
+    }
+
+    /// Values that are in either set.
+    pub fn union<'a>(&'a self, other: &'a AvlSet<T>) -> SetOp<'a, T> {
+        SetOp::new(self, other, true, true, true)
+    }
+
+    /// Values that are in both sets.
+    pub fn intersection<'a>(&'a self, other: &'a AvlSet<T>) -> SetOp<'a, T> {
+        SetOp::new(self, other, false, true, false)
+    }
+
+    /// Values that are in this set but not in the other.
+    pub fn difference<'a>(&'a self, other: &'a AvlSet<T>) -> SetOp<'a, T> {
+        SetOp::new(self, other, true, false, false)
+    }
+
+    /// Values that are in exactly one of the sets.
+    pub fn symmetric_difference<'a>(&'a self, other: &'a AvlSet<T>) -> SetOp<'a, T> {
+        SetOp::new(self, other, true, false, true)
+    }
+
+    /// Check if all values of this set are in the other set.
+    pub fn is_subset(&self, other: &AvlSet<T>) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+
+    /// Check if all values of the other set are in this set.
+    pub fn is_superset(&self, other: &AvlSet<T>) -> bool {
+        other.is_subset(self)
+    }
+
+    /// Check if the sets have no values in common.
+    pub fn is_disjoint(&self, other: &AvlSet<T>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<T> FromIterator<T> for AvlSet<T>
+where
+    T: PartialOrd,
+{
+    /// Build a set from values in any order in one pass, see the
+    /// `FromIterator` implementation of `Tree`.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> AvlSet<T> {
+        AvlSet {
+            tree: iter.into_iter().map(|value| (value, ())).collect(),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a AvlSet<T>
+where
+    T: PartialOrd,
+{
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator over the values of a set in order.
+pub struct Iter<'a, T>(tree::Iter<'a, T, ()>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.0.next().map(|(value, _)| value)
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back().map(|(value, _)| value)
+    }
+}
+
+/// Iterator over the result of a set operation, in order.
+///
+/// The two sets are walked in parallel and each value is produced
+/// depending on whether it is only in the left set, in both sets, or
+/// only in the right set.
+pub struct SetOp<'a, T> {
+    left: Peekable<Iter<'a, T>>,
+    right: Peekable<Iter<'a, T>>,
+    keep_left: bool,
+    keep_both: bool,
+    keep_right: bool,
+}
+
+impl<'a, T> SetOp<'a, T>
+where
+    T: PartialOrd,
+{
+    fn new(
+        left: &'a AvlSet<T>,
+        right: &'a AvlSet<T>,
+        keep_left: bool,
+        keep_both: bool,
+        keep_right: bool,
+    ) -> SetOp<'a, T> {
+        SetOp {
+            left: left.iter().peekable(),
+            right: right.iter().peekable(),
+            keep_left,
+            keep_both,
+            keep_right,
+        }
+    }
+}
+
+impl<'a, T> Iterator for SetOp<'a, T>
+where
+    T: PartialOrd,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            match (self.left.peek(), self.right.peek()) {
+                (None, None) => return None,
+                // The values left on one side are either all kept or
+                // all dropped, so they are not taken when dropped.
+                (Some(_), None) if self.keep_left => return self.left.next(),
+                (None, Some(_)) if self.keep_right => return self.right.next(),
+                (Some(_), None) | (None, Some(_)) => return None,
+                (Some(left), Some(right)) => {
+                    if left < right {
+                        let value = self.left.next();
+                        if self.keep_left {
+                            return value;
+                        }
+                    } else if right < left {
+                        let value = self.right.next();
+                        if self.keep_right {
+                            return value;
+                        }
+                    } else {
+                        let value = self.left.next();
+                        self.right.next();
+                        if self.keep_both {
+                            return value;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T> FusedIterator for SetOp<'a, T> where T: PartialOrd {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(iter: SetOp<i32>) -> Vec<i32> {
+        iter.cloned().collect()
+    }
+
+    #[test]
+    fn test_basic() {
+        let mut set = AvlSet::new();
+        assert!(set.insert(3));
+        assert!(set.insert(1));
+        assert!(!set.insert(3));
+        assert!(set.contains(&1));
+        assert!(!set.contains(&2));
+        assert_eq!(set.len(), 2);
+        assert!(set.remove(&1));
+        assert!(!set.remove(&1));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![&3]);
+    }
+
+    #[test]
+    fn test_algebra() {
+        let a: AvlSet<i32> = (0..10).filter(|x| x % 2 == 0).collect();
+        let b: AvlSet<i32> = (0..10).filter(|x| x % 3 == 0).collect();
+        assert_eq!(collect(a.union(&b)), vec![0, 2, 3, 4, 6, 8, 9]);
+        assert_eq!(collect(a.intersection(&b)), vec![0, 6]);
+        assert_eq!(collect(a.difference(&b)), vec![2, 4, 8]);
+        assert_eq!(collect(b.difference(&a)), vec![3, 9]);
+        assert_eq!(collect(a.symmetric_difference(&b)), vec![2, 3, 4, 8, 9]);
+
+        let empty = AvlSet::new();
+        assert_eq!(collect(a.union(&empty)), vec![0, 2, 4, 6, 8]);
+        assert_eq!(collect(empty.intersection(&a)), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn test_fused() {
+        let a: AvlSet<i32> = (0..10).collect();
+        let b: AvlSet<i32> = (0..3).collect();
+        let mut iter = a.intersection(&b);
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.left.peek(), Some(&&3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let set: AvlSet<i32> = vec![5, 1, 4, 1, 5, 9, 2, 6].into_iter().collect();
+        assert_eq!(set.len(), 6);
+        assert_eq!(
+            set.iter().cloned().collect::<Vec<_>>(),
+            vec![1, 2, 4, 5, 6, 9]
+        );
+    }
+
+    #[test]
+    fn test_subset() {
+        let a: AvlSet<i32> = vec![2, 4].into_iter().collect();
+        let b: AvlSet<i32> = (0..5).collect();
+        let c: AvlSet<i32> = vec![1, 3].into_iter().collect();
+        assert!(a.is_subset(&b));
+        assert!(!b.is_subset(&a));
+        assert!(b.is_superset(&a));
+        assert!(a.is_subset(&a));
+        assert!(a.is_disjoint(&c));
+        assert!(!b.is_disjoint(&c));
+        assert!(AvlSet::new().is_subset(&a));
+    }
+}
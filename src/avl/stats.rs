@@ -0,0 +1,350 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Statistics over the AVL tree.
+
+use super::multiset::MultiSet;
+use super::time::Timestamp;
+use super::tree::{height, Inner, Tree};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+/// Which samples a `QuantileWindow` keeps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    /// Keep the most recent number of samples.
+    Count(usize),
+    /// Keep the samples that are younger than a duration, measured in
+    /// the same unit as the timestamps.
+    Duration(Timestamp),
+}
+
+/// Quantiles over a sliding window of samples.
+///
+/// The samples in the window are kept in a multiset ordered by value,
+/// with counts in each subtree, so adding a sample, evicting one and
+/// finding a quantile all take O(log n). Values must have a total
+/// order, so for example floating point samples must not be NaN.
+pub struct QuantileWindow<T> {
+    window: Window,
+    /// Samples in arrival order, for eviction.
+    samples: VecDeque<(Timestamp, T)>,
+    values: MultiSet<T>,
+}
+
+impl<T> QuantileWindow<T>
+where
+    T: PartialOrd + Clone,
+{
+    pub fn new(window: Window) -> QuantileWindow<T> {
+        QuantileWindow {
+            window,
+            samples: VecDeque::new(),
+            values: MultiSet::new(),
+        }
+    }
+
+    /// Number of samples in the window.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Add a sample taken at a timestamp, evicting the samples that
+    /// fall out of the window.
+    ///
+    /// Timestamps are expected to not decrease between calls.
+    pub fn insert(&mut self, ts: Timestamp, value: T) {
+        self.values.insert(value.clone());
+        self.samples.push_back((ts, value));
+        match self.window {
+            Window::Count(count) => {
+                while self.samples.len() > count {
+                    self.evict();
+                }
+            }
+            Window::Duration(_) => self.advance(ts),
+        }
+    }
+
+    /// Evict the samples that are out of the window at a timestamp.
+    ///
+    /// This only has an effect for time-based windows.
+    pub fn advance(&mut self, now: Timestamp) {
+        if let Window::Duration(duration) = self.window {
+            while self
+                .samples
+                .front()
+                .is_some_and(|(ts, _)| ts.saturating_add(duration) <= now)
+            {
+                self.evict();
+            }
+        }
+    }
+
+    /// Remove the oldest sample.
+    fn evict(&mut self) {
+        if let Some((_, value)) = self.samples.pop_front() {
+            self.values.remove(&value);
+        }
+    }
+
+    /// Get the sample at a quantile between 0 and 1 using the nearest
+    /// rank method, or `None` if the window is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between 0 and 1.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        assert!((0.0..=1.0).contains(&q), "quantile out of range");
+        let len = self.values.len();
+        let rank = (q * len as f64).ceil() as usize;
+        self.values.nth(rank.max(1) - 1)
+    }
+
+    pub fn median(&self) -> Option<&T> {
+        self.quantile(0.5)
+    }
+
+    pub fn min(&self) -> Option<&T> {
+        self.quantile(0.0)
+    }
+
+    pub fn max(&self) -> Option<&T> {
+        self.quantile(1.0)
+    }
+}
+
+/// Shape of a tree, see `Tree::stats`.
+///
+/// Depths count the root as 1, so a leaf of a tree with a single node
+/// is at depth 1 and the deepest leaf is at the height of the tree.
+/// The `Display` implementation writes a report of the statistics.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TreeStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub height: usize,
+    /// Number of nodes with each balance factor, which is the height
+    /// of the right subtree minus the height of the left subtree.
+    pub balance: BTreeMap<isize, usize>,
+    /// Number of leaves at each depth.
+    pub leaf_depths: BTreeMap<usize, usize>,
+}
+
+impl TreeStats {
+    fn visit<K, V, S>(&mut self, node: &Option<Inner<K, V, S>>, depth: usize) {
+        if let Some(node) = node {
+            self.nodes += 1;
+            let balance = height(&node.right) - height(&node.left);
+            *self.balance.entry(balance).or_insert(0) += 1;
+            if node.left.is_none() && node.right.is_none() {
+                self.leaves += 1;
+                *self.leaf_depths.entry(depth).or_insert(0) += 1;
+            }
+            self.visit(&node.left, depth + 1);
+            self.visit(&node.right, depth + 1);
+        }
+    }
+
+    pub fn min_leaf_depth(&self) -> Option<usize> {
+        self.leaf_depths.keys().next().cloned()
+    }
+
+    pub fn max_leaf_depth(&self) -> Option<usize> {
+        self.leaf_depths.keys().next_back().cloned()
+    }
+
+    pub fn average_leaf_depth(&self) -> Option<f64> {
+        if self.leaves == 0 {
+            return None;
+        }
+        let total: usize = self
+            .leaf_depths
+            .iter()
+            .map(|(depth, count)| depth * count)
+            .sum();
+        Some(total as f64 / self.leaves as f64)
+    }
+
+    /// Smallest possible height of a binary tree with the same number
+    /// of nodes, which is ⌈log2(n + 1)⌉.
+    pub fn optimal_height(&self) -> usize {
+        (usize::BITS - self.nodes.leading_zeros()) as usize
+    }
+
+    /// Ratio of the height to the optimal height. An AVL tree is never
+    /// more than about 1.44 times higher than optimal, and the ratio of
+    /// an empty tree is 1.
+    pub fn height_ratio(&self) -> f64 {
+        match self.optimal_height() {
+            0 => 1.0,
+            optimal => self.height as f64 / optimal as f64,
+        }
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "nodes: {}", self.nodes)?;
+        writeln!(f, "leaves: {}", self.leaves)?;
+        writeln!(
+            f,
+            "height: {} (optimal {}, ratio {:.2})",
+            self.height,
+            self.optimal_height(),
+            self.height_ratio()
+        )?;
+        if let (Some(min), Some(max), Some(average)) = (
+            self.min_leaf_depth(),
+            self.max_leaf_depth(),
+            self.average_leaf_depth(),
+        ) {
+            writeln!(
+                f,
+                "leaf depth: min {}, max {}, average {:.2}",
+                min, max, average
+            )?;
+        }
+        writeln!(f, "leaf depths:")?;
+        for (depth, count) in &self.leaf_depths {
+            writeln!(f, "  {:>4}: {}", depth, count)?;
+        }
+        writeln!(f, "balance factors:")?;
+        for (balance, count) in &self.balance {
+            writeln!(f, "  {:>4}: {}", balance, count)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V, S> Tree<K, V, S> {
+    /// Collect statistics about the shape of the tree by visiting all
+    /// nodes.
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            height: height(&self.root) as usize,
+            ..TreeStats::default()
+        };
+        stats.visit(&self.root, 1);
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_stats() {
+        let mut tree = Tree::new();
+        assert_eq!(tree.stats(), TreeStats::default());
+        for key in 0..7 {
+            tree.insert(key, ());
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.nodes, 7);
+        assert_eq!(stats.height, 3);
+        assert_eq!(stats.balance.into_iter().collect::<Vec<_>>(), vec![(0, 7)]);
+
+        tree.insert(7, ());
+        let stats = tree.stats();
+        assert_eq!(stats.height, 4);
+        let total: usize = stats.balance.values().sum();
+        assert_eq!(total, 8);
+        assert!(stats.balance.keys().all(|balance| balance.abs() <= 1));
+        assert_eq!(stats.optimal_height(), 4);
+        assert_eq!(stats.leaves, 4);
+        assert_eq!(stats.min_leaf_depth(), Some(3));
+        assert_eq!(stats.max_leaf_depth(), Some(4));
+        assert_eq!(stats.average_leaf_depth(), Some(3.25));
+    }
+
+    #[test]
+    fn test_tree_stats_report() {
+        let mut tree = Tree::new();
+        assert_eq!(tree.stats().height_ratio(), 1.0);
+        for key in 0..1000 {
+            tree.insert(key, ());
+        }
+        let stats = tree.stats();
+        assert_eq!(stats.nodes, 1000);
+        assert_eq!(stats.leaf_depths.values().sum::<usize>(), stats.leaves);
+        assert!(stats.height_ratio() >= 1.0 && stats.height_ratio() < 1.45);
+
+        let mut tree = Tree::new();
+        for key in 0..3 {
+            tree.insert(key, ());
+        }
+        let report = tree.stats().to_string();
+        assert_eq!(
+            report.lines().collect::<Vec<_>>(),
+            vec![
+                "nodes: 3",
+                "leaves: 2",
+                "height: 2 (optimal 2, ratio 1.00)",
+                "leaf depth: min 2, max 2, average 2.00",
+                "leaf depths:",
+                "     2: 2",
+                "balance factors:",
+                "     0: 3",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_count_window() {
+        let mut window = QuantileWindow::new(Window::Count(100));
+        assert_eq!(window.median(), None);
+        for value in 1..=100 {
+            window.insert(0, value);
+        }
+        assert_eq!(window.median(), Some(&50));
+        assert_eq!(window.quantile(0.95), Some(&95));
+        assert_eq!(window.quantile(0.99), Some(&99));
+        assert_eq!(window.min(), Some(&1));
+        assert_eq!(window.max(), Some(&100));
+
+        // Evicts the samples 1 to 50.
+        for _ in 0..50 {
+            window.insert(0, 1000);
+        }
+        assert_eq!(window.len(), 100);
+        assert_eq!(window.min(), Some(&51));
+        assert_eq!(window.median(), Some(&100));
+        assert_eq!(window.quantile(0.51), Some(&1000));
+    }
+
+    #[test]
+    fn test_duration_window() {
+        let mut window = QuantileWindow::new(Window::Duration(10));
+        for ts in 0..20 {
+            // Duplicate latencies are counted separately.
+            window.insert(ts, (ts % 5) as f64);
+        }
+        assert_eq!(window.len(), 10);
+        assert_eq!(window.quantile(0.2), Some(&0.0));
+        assert_eq!(window.quantile(0.21), Some(&1.0));
+
+        window.advance(25);
+        assert_eq!(window.len(), 4);
+        assert_eq!(window.min(), Some(&1.0));
+        window.advance(100);
+        assert!(window.is_empty());
+        assert_eq!(window.max(), None);
+    }
+}
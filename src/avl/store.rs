@@ -0,0 +1,531 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Durable key-value store with a write-ahead log.
+//!
+//! The store keeps all data in memory in a tree and uses two files in
+//! a directory to make it durable:
+//!
+//! - `snapshot` holds the tree as of the last compaction, in the
+//!   format written by `Tree::write_to`.
+//!
+//! - `wal` holds every change made since the last compaction. Each
+//!   record consists of the length and CRC-32 of the payload, both as
+//!   little-endian `u32`, followed by the payload: an operation byte
+//!   and the key, and for inserts also the value.
+//!
+//! When the store is opened, the snapshot is loaded and the log is
+//! replayed on top of it. A record that is incomplete or has a bad
+//! checksum is the result of a write that did not complete, so the
+//! log is truncated at the start of that record.
+//!
+//! If appending a record fails, the log is truncated back to where the
+//! record started, so that a partial record cannot hide the records
+//! written after it. If even that fails, the store refuses all further
+//! changes, and reopening it recovers the log.
+//!
+//! Compaction writes a new snapshot next to the old one and renames it
+//! into place before the log is emptied. If a crash happens between
+//! the two, the log is replayed over the new snapshot, which gives the
+//! same result since replaying a record is idempotent.
+
+use super::codec::{invalid_data, Codec, Crc32};
+use super::tree::{Iter, Tree};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_FILE: &str = "snapshot";
+const SNAPSHOT_TEMP_FILE: &str = "snapshot.tmp";
+const LOG_FILE: &str = "wal";
+
+const OP_INSERT: u8 = 1;
+const OP_DELETE: u8 = 2;
+
+/// Default number of log records after which the log is compacted.
+const DEFAULT_COMPACTION_THRESHOLD: usize = 10_000;
+
+pub struct Store<K, V> {
+    dir: PathBuf,
+    tree: Tree<K, V>,
+    log: File,
+    /// Length of the log up to the end of the last complete record.
+    log_len: u64,
+    log_records: usize,
+    /// Set when the log could not be restored after a failed write.
+    failed: bool,
+    compaction_threshold: Option<usize>,
+    sync: bool,
+}
+
+impl<K, V> Store<K, V>
+where
+    K: Codec + PartialOrd,
+    V: Codec,
+{
+    /// Open the store in a directory, creating the directory if it
+    /// does not exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Store<K, V>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut tree = match File::open(dir.join(SNAPSHOT_FILE)) {
+            Ok(file) => Tree::read_from(&mut BufReader::new(file))?,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Tree::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(dir.join(LOG_FILE))?;
+        let (valid_len, log_records) = replay(&mut log, &mut tree)?;
+        if valid_len < log.metadata()?.len() {
+            log.set_len(valid_len)?;
+            log.sync_all()?;
+        }
+
+        Ok(Store {
+            dir,
+            tree,
+            log,
+            log_len: valid_len,
+            log_records,
+            failed: false,
+            compaction_threshold: Some(DEFAULT_COMPACTION_THRESHOLD),
+            sync: true,
+        })
+    }
+
+    /// Set the number of log records after which the log is compacted
+    /// automatically, or `None` to only compact explicitly.
+    ///
+    /// A change is kept even if the automatic compaction after it
+    /// fails. The compaction is then tried again after the next change,
+    /// and `compact` can be called to get the error.
+    pub fn set_compaction_threshold(&mut self, threshold: Option<usize>) {
+        self.compaction_threshold = threshold;
+    }
+
+    /// Set if the log should be synced to disk after every change.
+    ///
+    /// This is on by default. Turning it off is faster, but changes
+    /// can be lost on a crash.
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+    }
+
+    /// The tree with the current contents of the store.
+    pub fn tree(&self) -> &Tree<K, V> {
+        &self.tree
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.tree.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.tree.iter()
+    }
+
+    /// Insert a value under a key, returning the old value if the key
+    /// was already present.
+    ///
+    /// The change is written to the log before it is applied to the
+    /// tree.
+    pub fn insert(&mut self, key: K, value: V) -> io::Result<Option<V>> {
+        let mut payload = vec![OP_INSERT];
+        key.encode(&mut payload)?;
+        value.encode(&mut payload)?;
+        self.append(&payload)?;
+        let old_value = self.tree.insert(key, value);
+        self.maybe_compact();
+        Ok(old_value)
+    }
+
+    /// Remove a key, returning the value if the key was present.
+    ///
+    /// Nothing is written to the log if the key is not present.
+    pub fn remove(&mut self, key: &K) -> io::Result<Option<V>> {
+        if self.tree.get(key).is_none() {
+            return Ok(None);
+        }
+        let mut payload = vec![OP_DELETE];
+        key.encode(&mut payload)?;
+        self.append(&payload)?;
+        let value = self.tree.remove(key);
+        self.maybe_compact();
+        Ok(value)
+    }
+
+    /// Write the contents of the store to a new snapshot and empty the
+    /// log.
+    pub fn compact(&mut self) -> io::Result<()> {
+        let temp_path = self.dir.join(SNAPSHOT_TEMP_FILE);
+        {
+            let file = File::create(&temp_path)?;
+            let mut writer = BufWriter::new(file);
+            self.tree.write_to(&mut writer)?;
+            let file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, self.dir.join(SNAPSHOT_FILE))?;
+        sync_dir(&self.dir)?;
+
+        self.log.set_len(0)?;
+        self.log.sync_all()?;
+        self.log_len = 0;
+        self.log_records = 0;
+        Ok(())
+    }
+
+    fn append(&mut self, payload: &[u8]) -> io::Result<()> {
+        if self.failed {
+            return Err(io::Error::other(
+                "store failed after an incomplete write to the log",
+            ));
+        }
+        let mut crc = Crc32::new();
+        crc.update(payload);
+        let mut record = Vec::with_capacity(payload.len() + 8);
+        (payload.len() as u32).encode(&mut record)?;
+        crc.finish().encode(&mut record)?;
+        record.extend_from_slice(payload);
+
+        if let Err(err) = self.write_record(&record) {
+            // Remove whatever part of the record was written.
+            if self.log.set_len(self.log_len).is_err() {
+                self.failed = true;
+            }
+            return Err(err);
+        }
+        self.log_len += record.len() as u64;
+        self.log_records += 1;
+        Ok(())
+    }
+
+    fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        self.log.write_all(record)?;
+        if self.sync {
+            self.log.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Compact the log if it has reached the threshold.
+    ///
+    /// The change that triggered the compaction is already durable, so
+    /// a failure is not reported to its caller. The log keeps growing
+    /// and the next change tries again.
+    fn maybe_compact(&mut self) {
+        if let Some(threshold) = self.compaction_threshold {
+            if self.log_records >= threshold {
+                let _ = self.compact();
+            }
+        }
+    }
+}
+
+/// Replay the log on top of a tree.
+///
+/// Returns the length of the valid prefix of the log and the number
+/// of records in it.
+fn replay<K, V>(log: &mut File, tree: &mut Tree<K, V>) -> io::Result<(u64, usize)>
+where
+    K: Codec + PartialOrd,
+    V: Codec,
+{
+    log.seek(SeekFrom::Start(0))?;
+    let mut reader = BufReader::new(log);
+    let mut valid_len = 0;
+    let mut records = 0;
+    while let Some(payload) = read_record(&mut reader)? {
+        apply(&payload, tree)?;
+        valid_len += payload.len() as u64 + 8;
+        records += 1;
+    }
+    Ok((valid_len, records))
+}
+
+/// Read the payload of the next record in the log.
+///
+/// Returns `None` at the end of the log and when the record is torn or
+/// corrupt, since nothing after it can be trusted.
+fn read_record<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0; 8];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    let mut slice = &header[..];
+    let len = u32::decode(&mut slice)?;
+    let checksum = u32::decode(&mut slice)?;
+
+    let mut payload = Vec::new();
+    reader.take(len as u64).read_to_end(&mut payload)?;
+    if payload.len() != len as usize {
+        return Ok(None);
+    }
+    let mut crc = Crc32::new();
+    crc.update(&payload);
+    if crc.finish() != checksum {
+        return Ok(None);
+    }
+    Ok(Some(payload))
+}
+
+fn apply<K, V>(payload: &[u8], tree: &mut Tree<K, V>) -> io::Result<()>
+where
+    K: Codec + PartialOrd,
+    V: Codec,
+{
+    let mut reader = payload;
+    match u8::decode(&mut reader)? {
+        OP_INSERT => {
+            let key = K::decode(&mut reader)?;
+            let value = V::decode(&mut reader)?;
+            tree.insert(key, value);
+        }
+        OP_DELETE => {
+            let key = K::decode(&mut reader)?;
+            tree.remove(&key);
+        }
+        _ => return Err(invalid_data("unknown log operation")),
+    }
+    Ok(())
+}
+
+/// Sync a directory so that a rename in it is durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    /// Create an empty directory for a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("avl-store-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn open(dir: &Path) -> Store<u32, String> {
+        let mut store = Store::open(dir).unwrap();
+        store.set_sync(false);
+        store
+    }
+
+    fn fill(store: &mut Store<u32, String>, count: u32) {
+        for i in 0..count {
+            store.insert(i, format!("value {}", i)).unwrap();
+        }
+    }
+
+    fn keys(store: &Store<u32, String>) -> Vec<u32> {
+        store.iter().map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = test_dir("reopen");
+        {
+            let mut store = open(&dir);
+            fill(&mut store, 10);
+            assert!(store.insert(3, "three".to_string()).unwrap().is_some());
+            assert!(store.remove(&5).unwrap().is_some());
+            assert_eq!(store.remove(&5).unwrap(), None);
+        }
+        let store = open(&dir);
+        assert_eq!(keys(&store), vec![0, 1, 2, 3, 4, 6, 7, 8, 9]);
+        assert_eq!(store.get(&3), Some(&"three".to_string()));
+        assert_eq!(store.log_records, 12);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_torn_tail() {
+        let dir = test_dir("torn");
+        {
+            let mut store = open(&dir);
+            fill(&mut store, 10);
+        }
+
+        // Cut the log in the middle of the last record.
+        let log_path = dir.join(LOG_FILE);
+        let len = fs::metadata(&log_path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&log_path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        {
+            let mut store = open(&dir);
+            assert_eq!(keys(&store), (0..9).collect::<Vec<_>>());
+            // The torn record is removed, so new records are not
+            // hidden behind it.
+            store.insert(100, "new".to_string()).unwrap();
+        }
+        let store = open(&dir);
+        assert_eq!(store.len(), 10);
+        assert_eq!(store.get(&100), Some(&"new".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        let dir = test_dir("corrupt");
+        {
+            let mut store = open(&dir);
+            fill(&mut store, 10);
+        }
+
+        // Every record for keys below 10 has the same size, so flip a
+        // byte in the payload of the record for key 4.
+        let log_path = dir.join(LOG_FILE);
+        let mut bytes = fs::read(&log_path).unwrap();
+        let record_len = bytes.len() / 10;
+        bytes[4 * record_len + 10] ^= 0xFF;
+        fs::write(&log_path, &bytes).unwrap();
+
+        let store = open(&dir);
+        assert_eq!(keys(&store), vec![0, 1, 2, 3]);
+        assert_eq!(
+            fs::metadata(&log_path).unwrap().len(),
+            4 * record_len as u64
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_compaction() {
+        let dir = test_dir("compact");
+        {
+            let mut store = open(&dir);
+            store.set_compaction_threshold(Some(8));
+            fill(&mut store, 20);
+            assert_eq!(store.log_records, 4);
+            store.remove(&0).unwrap();
+            store.compact().unwrap();
+            assert_eq!(fs::metadata(dir.join(LOG_FILE)).unwrap().len(), 0);
+            store.insert(0, "zero".to_string()).unwrap();
+        }
+        let store = open(&dir);
+        assert_eq!(keys(&store), (0..20).collect::<Vec<_>>());
+        assert_eq!(store.get(&0), Some(&"zero".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_crash_during_compaction() {
+        let dir = test_dir("crash");
+        {
+            let mut store = open(&dir);
+            store.set_compaction_threshold(None);
+            fill(&mut store, 10);
+            store.remove(&2).unwrap();
+        }
+        // Simulate a crash after the snapshot was renamed into place
+        // but before the log was emptied.
+        let log = fs::read(dir.join(LOG_FILE)).unwrap();
+        {
+            let mut store = open(&dir);
+            store.compact().unwrap();
+        }
+        fs::write(dir.join(LOG_FILE), &log).unwrap();
+
+        // A stale temporary snapshot is ignored.
+        fs::write(dir.join(SNAPSHOT_TEMP_FILE), b"garbage").unwrap();
+
+        let store = open(&dir);
+        assert_eq!(keys(&store), vec![0, 1, 3, 4, 5, 6, 7, 8, 9]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_append() {
+        let dir = test_dir("failed");
+        let mut store = open(&dir);
+        fill(&mut store, 5);
+
+        // Neither writing to nor truncating a read-only log works, so
+        // the store refuses further changes.
+        store.log = File::open(dir.join(LOG_FILE)).unwrap();
+        assert!(store.insert(10, "ten".to_string()).is_err());
+        assert_eq!(store.get(&10), None);
+        store.log = OpenOptions::new()
+            .append(true)
+            .open(dir.join(LOG_FILE))
+            .unwrap();
+        assert!(store.insert(11, "eleven".to_string()).is_err());
+        assert!(store.remove(&1).is_err());
+        assert_eq!(store.len(), 5);
+        drop(store);
+
+        let mut store = open(&dir);
+        assert_eq!(keys(&store), vec![0, 1, 2, 3, 4]);
+        store.insert(10, "ten".to_string()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_compaction() {
+        let dir = test_dir("failed-compaction");
+        {
+            let mut store = open(&dir);
+            store.set_compaction_threshold(Some(4));
+            fill(&mut store, 3);
+
+            // The temporary snapshot cannot be created where there is a
+            // directory, so compaction fails.
+            fs::create_dir(dir.join(SNAPSHOT_TEMP_FILE)).unwrap();
+            assert!(store.compact().is_err());
+            assert_eq!(
+                store.insert(1, "one".to_string()).unwrap(),
+                Some("value 1".to_string())
+            );
+            assert_eq!(store.remove(&2).unwrap(), Some("value 2".to_string()));
+            assert_eq!(store.log_records, 5);
+
+            fs::remove_dir(dir.join(SNAPSHOT_TEMP_FILE)).unwrap();
+            store.insert(3, "three".to_string()).unwrap();
+            assert_eq!(store.log_records, 0);
+        }
+        let store = open(&dir);
+        assert_eq!(keys(&store), vec![0, 1, 3]);
+        assert_eq!(store.get(&1), Some(&"one".to_string()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,23 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Time type shared by the modules that keep track of time.
+
+/// Point in time, as a count of some unit from some start.
+///
+/// The unit is up to the user of each module, and only the order of
+/// timestamps and the differences between them matter. For example,
+/// `VersionedMap` can use a version counter, while the `SystemClock`
+/// of a `Cache` gives milliseconds since the Unix epoch.
+pub type Timestamp = u64;
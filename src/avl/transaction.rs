@@ -0,0 +1,281 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Transactions over the AVL tree.
+//!
+//! Changes in a transaction are applied to the tree right away, so
+//! reads in the transaction see them, and each change records how to
+//! undo it. Rolling back replays the undo log backwards, which takes
+//! time proportional to the number of changes rather than the size of
+//! the tree.
+
+use super::observe::{Event, Observers};
+use super::tree::{Summary, Tree};
+use std::mem;
+use std::result;
+
+/// How to undo a single change.
+enum Undo<K, V> {
+    /// The key was not present, so remove it.
+    Remove(K),
+    /// The key had a value, so put it back.
+    Restore(K, V),
+}
+
+/// Changes made to a tree as one unit, see `Tree::transaction`.
+///
+/// The observers of the tree are held by the transaction while it
+/// runs. Dropping it undoes the changes that are not committed and
+/// gives the observers back to the tree, which also happens when the
+/// function running the transaction panics.
+pub struct Transaction<'a, K: PartialOrd, V, S: Summary<K, V> = ()> {
+    tree: &'a mut Tree<K, V, S>,
+    undo: Vec<Undo<K, V>>,
+    observers: Observers<K, V>,
+}
+
+impl<'a, K, V, S> Transaction<'a, K, V, S>
+where
+    K: PartialOrd + Clone,
+    S: Summary<K, V>,
+{
+    /// Get the value stored under a key, including changes made in
+    /// the transaction.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.tree.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Insert a value under a key, returning the old value if the key
+    /// was present. The undo log keeps a clone of the old value.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        V: Clone,
+    {
+        let old = self.tree.insert(key.clone(), value);
+        let undo = match &old {
+            None => Undo::Remove(key),
+            Some(old) => Undo::Restore(key, old.clone()),
+        };
+        self.undo.push(undo);
+        old
+    }
+
+    /// Remove a key, returning the value if it was present. The undo
+    /// log keeps a clone of the value.
+    pub fn remove(&mut self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let old = self.tree.remove(key);
+        if let Some(value) = &old {
+            self.undo.push(Undo::Restore(key.clone(), value.clone()));
+        }
+        old
+    }
+
+    /// Keep the changes, sending one event for each key changed by the
+    /// transaction, from its value before the transaction to its value
+    /// now.
+    fn commit(&mut self) {
+        let undo = mem::take(&mut self.undo);
+        if self.observers.is_empty() {
+            return;
+        }
+        // Only the oldest undo entry of each key holds the value from
+        // before the transaction.
+        let mut before = Tree::new();
+        for undo in undo {
+            let (key, value) = match undo {
+                Undo::Remove(key) => (key, None),
+                Undo::Restore(key, value) => (key, Some(value)),
+            };
+            if before.get(&key).is_none() {
+                before.insert(key, value);
+            }
+        }
+        for (key, old) in before.iter() {
+            let event = match (old, self.tree.get(key)) {
+                (None, Some(new)) => Event::Inserted(key, new),
+                (Some(old), Some(new)) => Event::Replaced(key, old, new),
+                (Some(old), None) => Event::Removed(key, old),
+                (None, None) => continue,
+            };
+            self.observers.notify(&event);
+        }
+    }
+}
+
+impl<'a, K, V, S> Drop for Transaction<'a, K, V, S>
+where
+    K: PartialOrd,
+    S: Summary<K, V>,
+{
+    /// Undo the changes that are not committed, newest first, and give
+    /// the observers back to the tree.
+    fn drop(&mut self) {
+        while let Some(undo) = self.undo.pop() {
+            match undo {
+                Undo::Remove(key) => {
+                    self.tree.remove(&key);
+                }
+                Undo::Restore(key, value) => {
+                    self.tree.insert(key, value);
+                }
+            }
+        }
+        self.tree.observers = mem::take(&mut self.observers);
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Clone,
+    S: Summary<K, V>,
+{
+    /// Run a function that changes the tree as one unit.
+    ///
+    /// If the function returns an error, all its changes are undone
+    /// and the tree holds the same entries as before. Observers are
+    /// not called during the transaction. When it succeeds, they get
+    /// one event for each key whose value changed, and nothing is sent
+    /// for a transaction that is rolled back.
+    ///
+    /// If the function panics, its changes are undone the same way
+    /// while unwinding, and the observers are kept.
+    pub fn transaction<T, E, F>(&mut self, f: F) -> result::Result<T, E>
+    where
+        F: FnOnce(&mut Transaction<K, V, S>) -> result::Result<T, E>,
+    {
+        let observers = mem::take(&mut self.observers);
+        let mut tx = Transaction {
+            tree: self,
+            undo: Vec::new(),
+            observers,
+        };
+        let result = f(&mut tx);
+        if result.is_ok() {
+            tx.commit();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::avl::observe::{Batch, OwnedEvent};
+    use crate::avl::tree::Size;
+    use std::panic;
+
+    fn entries(tree: &Tree<i32, i32, Size>) -> Vec<(i32, i32)> {
+        tree.iter().map(|(k, v)| (*k, *v)).collect()
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut tree: Tree<i32, i32, Size> = Tree::default();
+        for key in 0..100 {
+            tree.insert(key, key);
+        }
+        let before = entries(&tree);
+
+        let result: Result<(), &str> = tree.transaction(|tx| {
+            for key in 50..150 {
+                tx.insert(key, -key);
+            }
+            assert_eq!(tx.get(&60), Some(&-60));
+            for key in 0..30 {
+                tx.remove(&key);
+            }
+            assert_eq!(tx.insert(60, 0), Some(-60));
+            assert_eq!(tx.remove(&10), None);
+            assert_eq!(tx.remove(&40), Some(40));
+            assert_eq!(tx.len(), 119);
+            Err("failed")
+        });
+        assert_eq!(result, Err("failed"));
+        assert_eq!(entries(&tree), before);
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.summary(), Some(&Size(100)));
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut tree = Tree::new();
+        tree.insert(1, "one");
+        tree.insert(2, "two");
+        let batch = Batch::new();
+        tree.observe(batch.observer());
+
+        let result: Result<usize, ()> = tree.transaction(|tx| {
+            tx.insert(3, "three");
+            tx.insert(1, "uno");
+            tx.insert(1, "eins");
+            tx.remove(&2);
+            tx.insert(4, "four");
+            tx.remove(&4);
+            Ok(tx.len())
+        });
+        assert_eq!(result, Ok(2));
+        assert_eq!(tree.get(&1), Some(&"eins"));
+        assert_eq!(tree.get(&2), None);
+        assert_eq!(
+            batch.take(),
+            vec![
+                OwnedEvent::Replaced(1, "one", "eins"),
+                OwnedEvent::Removed(2, "two"),
+                OwnedEvent::Inserted(3, "three"),
+            ]
+        );
+
+        let result: Result<(), ()> = tree.transaction(|tx| {
+            tx.insert(5, "five");
+            Err(())
+        });
+        assert_eq!(result, Err(()));
+        assert!(batch.is_empty());
+        tree.insert(6, "six");
+        assert_eq!(batch.len(), 1);
+    }
+
+    #[test]
+    fn test_panic() {
+        let mut tree = Tree::new();
+        tree.insert(1, "one");
+        let batch = Batch::new();
+        tree.observe(batch.observer());
+
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _: Result<(), ()> = tree.transaction(|tx| {
+                tx.insert(1, "uno");
+                tx.insert(2, "two");
+                panic!("failed");
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(tree.get(&1), Some(&"one"));
+        assert_eq!(tree.get(&2), None);
+        assert!(batch.is_empty());
+        tree.insert(3, "three");
+        assert_eq!(batch.take(), vec![OwnedEvent::Inserted(3, "three")]);
+    }
+}
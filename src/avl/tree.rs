@@ -12,9 +12,16 @@
 // implied.  See the License for the specific language governing
 // permissions and limitations under the License.
 
-use std::cmp::max;
-use std::fmt::Display;
-use std::result;
+use super::observe::{Event, ObserverId, Observers};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::{self, Vec};
+use core::cmp::{max, Ordering};
+use core::fmt::Display;
+use core::iter::FromIterator;
+use core::ops::{Bound, RangeBounds};
+use core::{mem, ptr, result};
 
 #[derive(PartialEq, Debug)]
 pub enum Error {
@@ -23,37 +30,120 @@ pub enum Error {
 
 pub type Result<T> = result::Result<T, Error>;
 
-struct Node<K, V> {
-    key: K,
-    value: V,
+/// Summary of the entries in a subtree.
+///
+/// Each node stores the summary of the subtree rooted at it, which is
+/// recomputed from the entry of the node and the summaries of its
+/// children whenever the subtree changes, including when it is
+/// rotated. This makes it possible to answer queries over ranges of
+/// the tree, such as order statistics, without visiting every node.
+pub trait Summary<K, V> {
+    fn summarize(key: &K, value: &V, left: Option<&Self>, right: Option<&Self>) -> Self;
+
+    /// Push a change that is pending in the summary of a node down to
+    /// the value of the node and the summaries of its children.
+    ///
+    /// This is called before a node is rotated or its children are
+    /// changed, so that summaries can hold changes to a whole subtree
+    /// that have not been applied to the entries yet. Summaries that
+    /// never hold pending changes can use the default, which does
+    /// nothing.
+    fn push_down(&mut self, _value: &mut V, _left: Option<&mut Self>, _right: Option<&mut Self>) {}
+}
+
+/// The empty summary, used by plain trees.
+impl<K, V> Summary<K, V> for () {
+    fn summarize(_key: &K, _value: &V, _left: Option<&()>, _right: Option<&()>) {}
+}
+
+/// Summary that assigns a weight to each entry and sums them over the
+/// subtree, used for order statistics.
+pub trait Weight<K, V>: Summary<K, V> {
+    /// Weight of a single entry.
+    fn weight(key: &K, value: &V) -> usize;
+
+    /// Total weight of a subtree.
+    fn total(&self) -> usize;
+}
+
+/// Summary counting the entries in each subtree, which makes the
+/// position of an entry available.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Size(pub usize);
+
+impl<K, V> Summary<K, V> for Size {
+    fn summarize(_key: &K, _value: &V, left: Option<&Size>, right: Option<&Size>) -> Size {
+        Size(1 + left.map_or(0, |s| s.0) + right.map_or(0, |s| s.0))
+    }
+}
+
+impl<K, V> Weight<K, V> for Size {
+    fn weight(_key: &K, _value: &V) -> usize {
+        1
+    }
+
+    fn total(&self) -> usize {
+        self.0
+    }
+}
+
+pub(crate) struct Node<K, V, S> {
+    pub(crate) key: K,
+    pub(crate) value: V,
     height: isize,
-    left: Option<Inner<K, V>>,
-    right: Option<Inner<K, V>>,
+    pub(crate) summary: S,
+    pub(crate) left: Option<Inner<K, V, S>>,
+    pub(crate) right: Option<Inner<K, V, S>>,
 }
 
 /// Convenience declaration of an inner subtree
-type Inner<K, V> = Box<Node<K, V>>;
+pub(crate) type Inner<K, V, S> = Box<Node<K, V, S>>;
 
 /// Helper function to compute height of an inner tree, which can be
 /// optional.
-fn height<K, V>(node: &Option<Inner<K, V>>) -> isize {
+pub(crate) fn height<K, V, S>(node: &Option<Inner<K, V, S>>) -> isize {
     node.as_ref().map_or(0, |n| n.height)
 }
 
+/// Check if a key is after the start of a range.
+pub(crate) fn after_start<K: PartialOrd>(key: &K, start: Bound<&K>) -> bool {
+    match start {
+        Bound::Included(start) => key >= start,
+        Bound::Excluded(start) => key > start,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Check if a key is before the end of a range.
+pub(crate) fn before_end<K: PartialOrd>(key: &K, end: Bound<&K>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end,
+        Bound::Excluded(end) => key < end,
+        Bound::Unbounded => true,
+    }
+}
+
+/// Total weight of an inner tree, which can be optional.
+pub(crate) fn total<K, V, S: Weight<K, V>>(node: &Option<Inner<K, V, S>>) -> usize {
+    node.as_ref().map_or(0, |n| n.summary.total())
+}
+
 //     (4)           (2)
 //     / \           / \
 //   (2) [5]  ==>  [1] (4)
 //   / \               / \
 // [1] [3]           [3] [5]
-fn rotate_right<K, V>(mut root: Inner<K, V>) -> Inner<K, V>
+fn rotate_right<K, V, S>(mut root: Inner<K, V, S>) -> Inner<K, V, S>
 where
-    K: PartialOrd,
+    S: Summary<K, V>,
 {
+    root.push();
     let mut new_root = root.left.take().expect("no left subtree");
+    new_root.push();
     root.left = new_root.right.take();
-    root.update_height();
+    root.update();
     new_root.right = Some(root);
-    new_root.update_height();
+    new_root.update();
     new_root
 }
 
@@ -62,22 +152,24 @@ where
 //  [1] (4)    ==>   (2) [5]
 //      / \          / \
 //    [3] [5]      [1] [3]
-fn rotate_left<K, V>(mut root: Inner<K, V>) -> Inner<K, V>
+fn rotate_left<K, V, S>(mut root: Inner<K, V, S>) -> Inner<K, V, S>
 where
-    K: PartialOrd,
+    S: Summary<K, V>,
 {
-    let mut new_root = root.right.take().expect("no left subtree");
+    root.push();
+    let mut new_root = root.right.take().expect("no right subtree");
+    new_root.push();
     root.right = new_root.left.take();
-    root.update_height();
+    root.update();
     new_root.left = Some(root);
-    new_root.update_height();
+    new_root.update();
     new_root
 }
 
 /// Rebalance an inner tree, returning the new root.
-fn rebalance<K, V>(mut root: Inner<K, V>) -> Inner<K, V>
+pub(crate) fn rebalance<K, V, S>(mut root: Inner<K, V, S>) -> Inner<K, V, S>
 where
-    K: PartialOrd,
+    S: Summary<K, V>,
 {
     let balance = height(&root.left) - height(&root.right);
     if balance > 1 {
@@ -99,65 +191,664 @@ where
     }
 }
 
+/// Remaining subtree and the node taken out of it.
+pub(crate) type Taken<K, V, S> = (Option<Inner<K, V, S>>, Inner<K, V, S>);
+
 /// Take out the smallest node from an inner subtree.
 ///
-/// Returns the smallest node and the new tree resulting from removing
-/// the smallest node. The smallest node will always be defined since
-/// the tree is non-empty, but the resulting tree can become an empty
-/// tree.
-fn take_smallest<K, V>(mut root: Inner<K, V>) -> (Option<Inner<K, V>>, Inner<K, V>) {
+/// Returns the new tree resulting from removing the smallest node
+/// together with the smallest node. The smallest node will always be
+/// defined since the tree is non-empty, but the resulting tree can
+/// become an empty tree.
+pub(crate) fn take_smallest<K, V, S>(mut root: Inner<K, V, S>) -> Taken<K, V, S>
+where
+    S: Summary<K, V>,
+{
+    root.push();
     if let Some(top) = root.left.take() {
         let (new_root, node) = take_smallest(top);
         root.left = new_root;
-        (Some(root), node)
+        root.update();
+        (Some(rebalance(root)), node)
     } else {
         let new_root = root.right.take();
         (new_root, root)
     }
 }
 
-impl<K, V> Node<K, V>
+/// Join two subtrees with a node between them, where all entries of
+/// the left subtree come before the node and all entries of the right
+/// subtree after it.
+///
+/// The node is placed where the heights of the subtrees meet and the
+/// tree is rebalanced on the way back up, so this takes time
+/// proportional to the difference in height.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) fn join<K, V, S>(
+    left: Option<Inner<K, V, S>>,
+    mut middle: Inner<K, V, S>,
+    right: Option<Inner<K, V, S>>,
+) -> Inner<K, V, S>
 where
-    K: std::cmp::PartialOrd,
+    S: Summary<K, V>,
 {
-    fn new(key: K, value: V) -> Node<K, V> {
+    let balance = height(&left) - height(&right);
+    if balance > 1 {
+        let mut root = left.unwrap();
+        root.push();
+        root.right = Some(join(root.right.take(), middle, right));
+        root.update();
+        rebalance(root)
+    } else if balance < -1 {
+        let mut root = right.unwrap();
+        root.push();
+        root.left = Some(join(left, middle, root.left.take()));
+        root.update();
+        rebalance(root)
+    } else {
+        middle.left = left;
+        middle.right = right;
+        middle.update();
+        middle
+    }
+}
+
+/// Concatenate two subtrees, where all entries of the left subtree
+/// come before all entries of the right subtree.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) fn concat<K, V, S>(
+    left: Option<Inner<K, V, S>>,
+    right: Option<Inner<K, V, S>>,
+) -> Option<Inner<K, V, S>>
+where
+    S: Summary<K, V>,
+{
+    match right {
+        None => left,
+        Some(right) => {
+            let (right, middle) = take_smallest(right);
+            Some(join(left, middle, right))
+        }
+    }
+}
+
+/// Order of two keys, where keys that are neither less nor greater
+/// than each other are equal.
+pub(crate) fn compare<K: PartialOrd>(a: &K, b: &K) -> Ordering {
+    if a < b {
+        Ordering::Less
+    } else if a > b {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+/// Remove entries with the same key from entries sorted by key,
+/// keeping the last one as if the entries had been inserted in order.
+pub(crate) fn dedup_sorted<K: PartialOrd, V>(entries: &mut Vec<(K, V)>) {
+    entries.dedup_by(|later, kept| {
+        let same = later.0 == kept.0;
+        if same {
+            mem::swap(later, kept);
+        }
+        same
+    });
+}
+
+/// Copy a subtree with a function applied to each value, keeping its
+/// shape. The function is called in key order.
+pub(crate) fn map_node<K, V, W, S, F>(mut node: Node<K, V, S>, f: &mut F) -> Inner<K, W, S>
+where
+    S: Summary<K, V> + Summary<K, W>,
+    F: FnMut(&K, V) -> W,
+{
+    node.push();
+    let Node {
+        key,
+        value,
+        left,
+        right,
+        ..
+    } = node;
+    let left = left.map(|left| map_node(*left, f));
+    let value = f(&key, value);
+    let right = right.map(|right| map_node(*right, f));
+    join(left, Box::new(Node::new(key, value)), right)
+}
+
+/// Find the value stored under a key in a subtree.
+fn lookup<'a, K, V, S>(root: &'a Option<Inner<K, V, S>>, key: &K) -> Option<&'a V>
+where
+    K: PartialOrd,
+{
+    let mut current = root;
+    while let Some(node) = current {
+        if *key < node.key {
+            current = &node.left;
+        } else if *key > node.key {
+            current = &node.right;
+        } else {
+            return Some(&node.value);
+        }
+    }
+    None
+}
+
+/// Build a balanced subtree from the next `count` entries of an
+/// iterator over entries sorted by key.
+///
+/// The left and right subtrees differ in size by at most one, so the
+/// resulting subtree is balanced without any rotations.
+pub(crate) fn build_sorted<K, V, S, I>(entries: &mut I, count: usize) -> Option<Inner<K, V, S>>
+where
+    S: Summary<K, V>,
+    I: Iterator<Item = (K, V)>,
+{
+    if count == 0 {
+        return None;
+    }
+    let left = build_sorted(entries, count / 2);
+    let (key, value) = entries.next().expect("too few entries");
+    let mut node = Box::new(Node::new(key, value));
+    node.left = left;
+    node.right = build_sorted(entries, count - count / 2 - 1);
+    node.update();
+    Some(node)
+}
+
+/// Move all entries of a subtree into a vector, in key order.
+pub(crate) fn drain_into<K, V, S>(root: Option<Inner<K, V, S>>, entries: &mut Vec<(K, V)>)
+where
+    S: Summary<K, V>,
+{
+    if let Some(mut node) = root {
+        node.push();
+        let Node {
+            key,
+            value,
+            left,
+            right,
+            ..
+        } = *node;
+        drain_into(left, entries);
+        entries.push((key, value));
+        drain_into(right, entries);
+    }
+}
+
+impl<K, V, S> Node<K, V, S>
+where
+    S: Summary<K, V>,
+{
+    pub(crate) fn new(key: K, value: V) -> Node<K, V, S> {
+        let summary = S::summarize(&key, &value, None, None);
         Node {
-            key: key,
-            value: value,
+            key,
+            value,
             height: 1,
+            summary,
             left: None,
             right: None,
         }
     }
 
-    fn update_height(&mut self) {
+    /// Push any change pending in the summary of the node down to its
+    /// value and children.
+    pub(crate) fn push(&mut self) {
+        let left = self.left.as_mut().map(|n| &mut n.summary);
+        let right = self.right.as_mut().map(|n| &mut n.summary);
+        self.summary.push_down(&mut self.value, left, right);
+    }
+
+    /// Recompute the height and the summary of the node from its
+    /// children.
+    pub(crate) fn update(&mut self) {
         self.height = max(height(&self.left), height(&self.right)) + 1;
+        self.summary = S::summarize(
+            &self.key,
+            &self.value,
+            self.left.as_ref().map(|n| &n.summary),
+            self.right.as_ref().map(|n| &n.summary),
+        );
     }
 }
 
-pub struct Tree<K, V> {
-    root: Option<Inner<K, V>>,
+/// AVL tree mapping keys to values.
+///
+/// The tree can optionally maintain a `Summary` of each subtree, given
+/// by the `S` type parameter. Plain trees use the empty summary `()`
+/// and are created with `Tree::new`, while trees with other summaries
+/// are created with `Tree::default`.
+pub struct Tree<K, V, S = ()> {
+    pub(crate) root: Option<Inner<K, V, S>>,
+    pub(crate) len: usize,
+    pub(crate) observers: Observers<K, V>,
+}
+
+impl<K, V, S> Default for Tree<K, V, S> {
+    fn default() -> Tree<K, V, S> {
+        Tree {
+            root: None,
+            len: 0,
+            observers: Observers::default(),
+        }
+    }
 }
 
 impl<K, V> Tree<K, V>
 where
-    K: std::cmp::PartialOrd + Display,
+    K: PartialOrd,
 {
     pub fn new() -> Tree<K, V> {
-        Tree { root: None }
+        Tree::default()
+    }
+
+    /// Get a mutable reference to the value stored under a key.
+    ///
+    /// This is only available for trees without a summary, since
+    /// changing a value would make the summaries stale. Use
+    /// `Tree::update` for other trees.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        while let Some(node) = current {
+            if *key < node.key {
+                current = &mut node.left;
+            } else if *key > node.key {
+                current = &mut node.right;
+            } else {
+                return Some(&mut node.value);
+            }
+        }
+        None
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd,
+    S: Summary<K, V>,
+{
+    /// Build a tree in O(n) from entries that are sorted by key with
+    /// no duplicate keys.
+    ///
+    /// The caller is responsible for the order of the entries: the
+    /// tree will not be a search tree otherwise.
+    pub(crate) fn from_sorted(entries: Vec<(K, V)>) -> Tree<K, V, S> {
+        let len = entries.len();
+        let root = build_sorted(&mut entries.into_iter(), len);
+        Tree {
+            root,
+            len,
+            observers: Observers::default(),
+        }
+    }
+
+    /// Register an observer that is called before each insert, replace
+    /// and remove, and after each `modify`.
+    ///
+    /// Changes made through `get_mut` or to values kept by `retain` are
+    /// not observed.
+    pub fn observe<F>(&mut self, observer: F) -> ObserverId
+    where
+        F: FnMut(&Event<K, V>) + Send + Sync + 'static,
+    {
+        self.observers.add(Box::new(observer))
+    }
+
+    /// Remove an observer, returning false if it was not registered.
+    pub fn unobserve(&mut self, id: ObserverId) -> bool {
+        self.observers.remove(id)
     }
 
     pub fn height(&self) -> isize {
         height(&self.root)
     }
-    
+
+    /// Number of entries in the tree.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Insert value into tree under the given key.
+    ///
+    /// If the key was already present, the value is replaced and the
+    /// old value returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if !self.observers.is_empty() {
+            let event = match lookup(&self.root, &key) {
+                Some(old) => Event::Replaced(&key, old, &value),
+                None => Event::Inserted(&key, &value),
+            };
+            self.observers.notify(&event);
+        }
+        let old_root = self.root.take();
+        let (new_root, old_value) = self.insert_node(old_root, key, value);
+        self.root = Some(new_root);
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        old_value
+    }
+
+    /// Insert key and value into a new node, optionally performing rotations.
+    ///
+    /// The rotations might be done to preserve the balance between
+    /// the left and right branch of the tree and the root will be
+    /// returned (either the new or old root).
+    fn insert_node(
+        &self,
+        root: Option<Inner<K, V, S>>,
+        key: K,
+        value: V,
+    ) -> (Inner<K, V, S>, Option<V>) {
+        if let Some(mut node) = root {
+            node.push();
+            let old_value = if key < node.key {
+                let (left, old_value) = self.insert_node(node.left.take(), key, value);
+                node.left = Some(left);
+                old_value
+            } else if key > node.key {
+                let (right, old_value) = self.insert_node(node.right.take(), key, value);
+                node.right = Some(right);
+                old_value
+            } else {
+                let old_value = mem::replace(&mut node.value, value);
+                node.update();
+                return (node, Some(old_value));
+            };
+
+            node.update();
+            (rebalance(node), old_value)
+        } else {
+            (Box::new(Node::new(key, value)), None)
+        }
+    }
+
+    /// Delete a record by key from the tree.
+    ///
+    pub fn delete(&mut self, key: K) -> Result<()> {
+        self.remove(&key).map(|_| ()).ok_or(Error::NotFound)
+    }
+
+    /// Remove a record by key from the tree, returning the value if
+    /// the key was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if !self.observers.is_empty() {
+            if let Some(value) = lookup(&self.root, key) {
+                self.observers.notify(&Event::Removed(key, value));
+            }
+        }
+        let old_root = self.root.take();
+        let (new_root, value) = self.remove_node(old_root, key);
+        self.root = new_root;
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    fn remove_node(
+        &self,
+        root: Option<Inner<K, V, S>>,
+        key: &K,
+    ) -> (Option<Inner<K, V, S>>, Option<V>) {
+        match root {
+            None => (None, None),
+            Some(mut node) => {
+                node.push();
+                if *key < node.key {
+                    let (left, value) = self.remove_node(node.left.take(), key);
+                    node.left = left;
+                    node.update();
+                    (Some(rebalance(node)), value)
+                } else if *key > node.key {
+                    let (right, value) = self.remove_node(node.right.take(), key);
+                    node.right = right;
+                    node.update();
+                    (Some(rebalance(node)), value)
+                } else {
+                    // The root of the tree is the node to delete, so
+                    // either the tree is empty after the node is
+                    // deleted, or we need to promote either left or
+                    // right tree as the new root of the tree. If both
+                    // are present, the smallest node of the right
+                    // tree replaces the deleted node.
+                    let Node {
+                        value, left, right, ..
+                    } = *node;
+                    match (left, right) {
+                        (None, None) => (None, Some(value)),
+                        (None, Some(right)) => (Some(right), Some(value)),
+                        (Some(left), None) => (Some(left), Some(value)),
+                        (Some(left), Some(right)) => {
+                            let (right, mut inner) = take_smallest(right);
+                            inner.left = Some(left);
+                            inner.right = right;
+                            inner.update();
+                            (Some(rebalance(inner)), Some(value))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn find(&self, key: K) -> Option<&V> {
+        self.get(&key)
+    }
+
+    /// Get a reference to the value stored under a key.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        lookup(&self.root, key)
+    }
+
+    /// Modify the value stored under a key in place, returning false
+    /// if the key is not present.
+    ///
+    /// The summaries on the path to the entry are recomputed
+    /// afterwards, so this can be used on trees with a summary.
+    pub fn modify<F>(&mut self, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+    {
+        let found = Self::modify_node(&mut self.root, key, f);
+        if found && !self.observers.is_empty() {
+            if let Some(value) = lookup(&self.root, key) {
+                self.observers.notify(&Event::Modified(key, value));
+            }
+        }
+        found
+    }
+
+    fn modify_node<F>(root: &mut Option<Inner<K, V, S>>, key: &K, f: F) -> bool
+    where
+        F: FnOnce(&mut V),
+    {
+        match root {
+            None => false,
+            Some(node) => {
+                node.push();
+                let found = if *key < node.key {
+                    Self::modify_node(&mut node.left, key, f)
+                } else if *key > node.key {
+                    Self::modify_node(&mut node.right, key, f)
+                } else {
+                    f(&mut node.value);
+                    true
+                };
+                if found {
+                    node.update();
+                }
+                found
+            }
+        }
+    }
+
+    /// Summary of all entries in the tree, or `None` if the tree is
+    /// empty.
+    pub fn summary(&self) -> Option<&S> {
+        self.root.as_ref().map(|node| &node.summary)
+    }
+
+    /// Keep only the entries for which the predicate returns true.
+    ///
+    /// The predicate is called once for each entry in key order and
+    /// can modify the value. The tree is rebuilt in O(n) from the
+    /// remaining entries.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let mut entries = Vec::with_capacity(self.len);
+        drain_into(self.root.take(), &mut entries);
+        let observers = &mut self.observers;
+        entries.retain_mut(|(key, value)| {
+            let kept = keep(key, value);
+            if !kept && !observers.is_empty() {
+                observers.notify(&Event::Removed(key, value));
+            }
+            kept
+        });
+        self.len = entries.len();
+        self.root = build_sorted(&mut entries.into_iter(), self.len);
+    }
+
+    /// Turn the tree into a tree with the same keys and a function
+    /// applied to each value.
+    ///
+    /// The function is called once for each entry in key order. The
+    /// new tree has the same shape as the tree and no observers.
+    pub fn map_values<W, F>(mut self, mut f: F) -> Tree<K, W, S>
+    where
+        S: Summary<K, W>,
+        F: FnMut(&K, V) -> W,
+    {
+        Tree {
+            root: self.root.take().map(|root| map_node(*root, &mut f)),
+            len: self.len,
+            observers: Observers::default(),
+        }
+    }
+
+    /// Look up many keys at once, returning the values in the same
+    /// order as the keys.
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<&V>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+
+    /// Iterate over all entries of the tree in key order.
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
+        self.range(..)
+    }
+
+    /// Iterate over the entries with keys in a range, in key order.
+    ///
+    /// An empty iterator is returned if the start of the range is
+    /// after the end of the range.
+    pub fn range<R>(&self, range: R) -> Iter<'_, K, V, S>
+    where
+        R: RangeBounds<K>,
+    {
+        let mut front = Vec::new();
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if after_start(&node.key, range.start_bound()) {
+                front.push(node);
+                current = node.left.as_deref();
+            } else {
+                current = node.right.as_deref();
+            }
+        }
+
+        let mut back = Vec::new();
+        let mut current = self.root.as_deref();
+        while let Some(node) = current {
+            if before_end(&node.key, range.end_bound()) {
+                back.push(node);
+                current = node.right.as_deref();
+            } else {
+                current = node.left.as_deref();
+            }
+        }
+
+        let mut iter = Iter { front, back };
+        match (iter.front.last(), iter.back.last()) {
+            (Some(first), Some(last)) if first.key <= last.key => {}
+            _ => iter.clear(),
+        }
+        iter
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd,
+    S: Weight<K, V>,
+{
+    /// Total weight of all entries in the tree.
+    pub fn total_weight(&self) -> usize {
+        total(&self.root)
+    }
+
+    /// Find the entry covering a position, where each entry covers as
+    /// many positions as its weight and positions start at zero.
+    ///
+    /// With the `Size` summary, this is the entry at an index.
+    pub fn select(&self, mut index: usize) -> Option<(&K, &V)> {
+        let mut current = &self.root;
+        while let Some(node) = current {
+            let left = total(&node.left);
+            if index < left {
+                current = &node.left;
+                continue;
+            }
+            index -= left;
+            let weight = S::weight(&node.key, &node.value);
+            if index < weight {
+                return Some((&node.key, &node.value));
+            }
+            index -= weight;
+            current = &node.right;
+        }
+        None
+    }
+
+    /// Total weight of the entries with keys smaller than a key.
+    ///
+    /// With the `Size` summary, this is the index of the key, or of
+    /// the position where it would be inserted.
+    pub fn rank(&self, key: &K) -> usize {
+        let mut rank = 0;
+        let mut current = &self.root;
+        while let Some(node) = current {
+            if *key <= node.key {
+                current = &node.left;
+            } else {
+                rank += total(&node.left) + S::weight(&node.key, &node.value);
+                current = &node.right;
+            }
+        }
+        rank
+    }
+}
+
+impl<K, V, S> Tree<K, V, S>
+where
+    K: PartialOrd + Display,
+    S: Summary<K, V>,
+{
     pub fn pretty(&self) -> String {
         self.pretty_node(&self.root, String::new(), String::new(), String::new())
     }
 
     fn pretty_node(
         &self,
-        tree: &Option<Inner<K, V>>,
+        tree: &Option<Inner<K, V, S>>,
         left: String,
         mid: String,
         right: String,
@@ -191,93 +882,136 @@ where
             String::new()
         }
     }
+}
 
-    /// Insert value into tree under the given key.
-    pub fn insert(&mut self, key: K, value: V) {
-        let old_root = self.root.take();
-        let new_root = self.insert_node(old_root, key, value);
-        self.root = Some(new_root);
+/// Iterator over the entries of a tree in key order.
+///
+/// The iterator keeps one stack of nodes for each end of the range,
+/// where the top of each stack is the next node to yield from that
+/// end. The iteration is done when the two ends meet.
+pub struct Iter<'a, K, V, S = ()> {
+    front: Vec<&'a Node<K, V, S>>,
+    back: Vec<&'a Node<K, V, S>>,
+}
+
+impl<'a, K, V, S> Iter<'a, K, V, S> {
+    /// Iterator over all entries of a subtree.
+    pub(crate) fn over(root: Option<&'a Node<K, V, S>>) -> Iter<'a, K, V, S> {
+        let mut front = Vec::new();
+        let mut current = root;
+        while let Some(node) = current {
+            front.push(node);
+            current = node.left.as_deref();
+        }
+        let mut back = Vec::new();
+        let mut current = root;
+        while let Some(node) = current {
+            back.push(node);
+            current = node.right.as_deref();
+        }
+        Iter { front, back }
     }
 
-    /// Insert key and value into a new node, optionally performing rotations.
-    ///
-    /// The rotations might be done to preserve the balance between
-    /// the left and right branch of the tree and the root will be
-    /// returned (either the new or old root).
-    fn insert_node(&self, root: Option<Inner<K, V>>, key: K, value: V) -> Inner<K, V> {
-        if let Some(mut node) = root {
-            if key < node.key {
-                node.left = Some(self.insert_node(node.left.take(), key, value));
-            } else {
-                node.right = Some(self.insert_node(node.right.take(), key, value));
-            };
+    fn clear(&mut self) {
+        self.front.clear();
+        self.back.clear();
+    }
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S> {
+    type Item = (&'a K, &'a V);
 
-            node.update_height();
-            rebalance(node)
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = *self.front.last()?;
+        if ptr::eq(node, *self.back.last()?) {
+            self.clear();
         } else {
-            Box::new(Node::new(key, value))
+            self.front.pop();
+            let mut current = node.right.as_deref();
+            while let Some(next) = current {
+                self.front.push(next);
+                current = next.left.as_deref();
+            }
         }
+        Some((&node.key, &node.value))
     }
+}
 
-    /// Delete a record by key from the tree.
-    ///
-    pub fn delete(&mut self, key: K) -> Result<()> {
-        let old_root = self.root.take();
-        self.root = self.delete_node(old_root, key)?;
-        Ok(())
-    }
-
-    fn delete_node(&self, root: Option<Inner<K, V>>, key: K) -> Result<Option<Inner<K, V>>> {
-        match root {
-            None => Err(Error::NotFound),
-            Some(mut node) => {
-                if key < node.key {
-                    node.left = self.delete_node(node.left.take(), key)?;
-                    node.update_height();
-                    Ok(Some(rebalance(node)))
-                } else if key > node.key {
-                    node.right = self.delete_node(node.right.take(), key)?;
-                    node.update_height();
-                    Ok(Some(rebalance(node)))
-                } else {
-                    // The root of the tree is the node to delete, so
-                    // either the tree is empty efter the node is
-                    // deleted, or we need to promote either left or
-                    // right tree as the new root of the tree.
-                    match (node.left.take(), node.right.take()) {
-                        (None, None) => Ok(None),
-                        (None, Some(right)) => Ok(Some(right)),
-                        (Some(left), None) => Ok(Some(left)),
-                        (Some(left), Some(right)) => {
-                            let (new_root, node) = take_smallest(right);
-                            if let Some(mut inner) = new_root {
-                                inner.left = Some(left);
-                                inner.right = Some(node);
-                                Ok(Some(inner))
-                            } else {
-                                Ok(None)
-                            }
-                        }
-                    }
-                }
+impl<'a, K, V, S> DoubleEndedIterator for Iter<'a, K, V, S> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let node = *self.back.last()?;
+        if ptr::eq(node, *self.front.last()?) {
+            self.clear();
+        } else {
+            self.back.pop();
+            let mut current = node.left.as_deref();
+            while let Some(next) = current {
+                self.back.push(next);
+                current = next.right.as_deref();
             }
         }
+        Some((&node.key, &node.value))
     }
+}
 
-    pub fn find(&self, key: K) -> Option<&V> {
-        self.find_node(&self.root, key)
+impl<'a, K, V, S> IntoIterator for &'a Tree<K, V, S>
+where
+    K: PartialOrd,
+    S: Summary<K, V>,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, S>;
+
+    fn into_iter(self) -> Iter<'a, K, V, S> {
+        self.iter()
     }
+}
 
-    fn find_node<'a>(&'a self, root: &'a Option<Inner<K, V>>, key: K) -> Option<&'a V> {
-        root.as_ref().and_then(|node| {
-            if key < node.key {
-                self.find_node(&node.left, key)
-            } else if key > node.key {
-                self.find_node(&node.right, key)
-            } else {
-                Some(&node.value)
-            }
-        })
+impl<K, V, S> FromIterator<(K, V)> for Tree<K, V, S>
+where
+    K: PartialOrd,
+    S: Summary<K, V>,
+{
+    /// Build a tree from entries in any order by sorting them and
+    /// building the tree in one pass. If several entries have the same
+    /// key, the last one is kept, as if they had been inserted in
+    /// order.
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Tree<K, V, S> {
+        let mut entries: Vec<_> = iter.into_iter().collect();
+        entries.sort_by(|a, b| compare(&a.0, &b.0));
+        dedup_sorted(&mut entries);
+        Tree::from_sorted(entries)
+    }
+}
+
+/// Iterator that moves the entries out of a tree in key order.
+pub struct IntoIter<K, V>(vec::IntoIter<(K, V)>);
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        self.0.next()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<(K, V)> {
+        self.0.next_back()
+    }
+}
+
+impl<K, V, S> IntoIterator for Tree<K, V, S>
+where
+    S: Summary<K, V>,
+{
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        let mut entries = Vec::with_capacity(self.len);
+        drain_into(self.root.take(), &mut entries);
+        IntoIter(entries.into_iter())
     }
 }
 
@@ -285,7 +1019,7 @@ where
 mod tests {
     use super::*;
 
-    fn is_avl<K, V>(root: &Option<Inner<K, V>>) -> Option<isize> {
+    fn is_avl<K, V, S>(root: &Option<Inner<K, V, S>>) -> Option<isize> {
         if let Some(ref node) = root {
             if let Some(lh) = is_avl(&node.left) {
                 if let Some(rh) = is_avl(&node.right) {
@@ -300,12 +1034,8 @@ mod tests {
         }
     }
 
-    fn is_avl_tree<K, V>(tree: &Tree<K, V>) -> bool {
-        if let Some(_) = is_avl(&tree.root) {
-            true
-        } else {
-            false
-        }
+    fn is_avl_tree<K, V, S>(tree: &Tree<K, V, S>) -> bool {
+        is_avl(&tree.root).is_some()
     }
 
     #[test]
@@ -315,14 +1045,17 @@ mod tests {
                 key: 1,
                 value: 1,
                 height: 3,
+                summary: (),
                 left: Some(Box::new(Node {
                     key: 2,
                     value: 2,
                     height: 2,
+                    summary: (),
                     left: Some(Box::new(Node {
                         key: 3,
                         value: 3,
                         height: 1,
+                        summary: (),
                         left: None,
                         right: None,
                     })),
@@ -330,6 +1063,8 @@ mod tests {
                 })),
                 right: None,
             })),
+            len: 3,
+            observers: Observers::default(),
         };
         assert!(!is_avl_tree(&tree));
     }
@@ -363,37 +1098,265 @@ mod tests {
             assert_eq!(tree.find(2 * 11 - i), Some(&(i * i * i)));
 
             assert_eq!(tree.delete(i), Ok(()));
-            assert!(
-                is_avl_tree(&tree),
-                format!("Tree was not AVL:\n{}", tree.pretty())
-            );
+            assert!(is_avl_tree(&tree), "Tree was not AVL:\n{}", tree.pretty());
             assert_eq!(tree.find(i), None);
             assert_eq!(tree.find(2 * 11 - i), Some(&(i * i * i)));
 
             assert_eq!(tree.delete(2 * 11 - i), Ok(()));
-            assert!(
-                is_avl_tree(&tree),
-                format!("Tree was not AVL:\n{}", tree.pretty())
-            );
+            assert!(is_avl_tree(&tree), "Tree was not AVL:\n{}", tree.pretty());
             assert_eq!(tree.find(i), None);
             assert_eq!(tree.find(2 * 11 - i), None);
         }
     }
 
+    #[test]
+    fn test_insert_replace() {
+        let mut tree = Tree::new();
+        assert_eq!(tree.insert(1, "one"), None);
+        assert_eq!(tree.insert(2, "two"), None);
+        assert_eq!(tree.insert(1, "uno"), Some("one"));
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.get(&1), Some(&"uno"));
+    }
+
+    #[test]
+    fn test_remove() {
+        // Remove keys in an order that exercises removal of inner
+        // nodes with two children.
+        let mut tree = Tree::new();
+        for i in 0..64 {
+            tree.insert(i, i * i);
+        }
+        for i in (0..64).map(|i| (i * 37) % 64) {
+            assert_eq!(tree.remove(&i), Some(i * i));
+            assert!(is_avl_tree(&tree), "Tree was not AVL:\n{}", tree.pretty());
+            assert_eq!(tree.get(&i), None);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.remove(&1), None);
+        assert_eq!(tree.delete(1), Err(Error::NotFound));
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut tree = Tree::new();
+        for i in (0..20).rev() {
+            tree.insert(i * 2, i);
+        }
+        let keys: Vec<_> = tree.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+        let keys: Vec<_> = tree.iter().rev().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (0..20).rev().map(|i| i * 2).collect::<Vec<_>>());
+
+        let mut iter = tree.iter();
+        assert_eq!(iter.next(), Some((&0, &0)));
+        assert_eq!(iter.next_back(), Some((&38, &19)));
+        assert_eq!(iter.count(), 18);
+    }
+
+    #[test]
+    fn test_range() {
+        let mut tree = Tree::new();
+        for i in 0..20 {
+            tree.insert(i * 2, i);
+        }
+        let keys = |iter: Iter<i32, i32>| iter.map(|(k, _)| *k).collect::<Vec<i32>>();
+        assert_eq!(keys(tree.range(3..9)), vec![4, 6, 8]);
+        assert_eq!(keys(tree.range(4..=8)), vec![4, 6, 8]);
+        assert_eq!(keys(tree.range(4..8)), vec![4, 6]);
+        assert_eq!(keys(tree.range(..3)), vec![0, 2]);
+        assert_eq!(keys(tree.range(35..)), vec![36, 38]);
+        assert_eq!(keys(tree.range(5..6)), Vec::<i32>::new());
+        assert_eq!(
+            keys(tree.range((Bound::Included(10), Bound::Excluded(4)))),
+            Vec::<i32>::new()
+        );
+        assert_eq!(keys(tree.range(40..)), Vec::<i32>::new());
+        let keys: Vec<_> = tree.range(3..9).rev().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![8, 6, 4]);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut tree = Tree::new();
+        for i in 0..10 {
+            tree.insert(i, i);
+        }
+        *tree.get_mut(&4).unwrap() = 40;
+        assert_eq!(tree.get(&4), Some(&40));
+        assert_eq!(tree.get_mut(&10), None);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut tree = Tree::new();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        tree.retain(|k, v| {
+            *v *= 2;
+            k % 3 == 0
+        });
+        assert!(is_avl_tree(&tree));
+        assert_eq!(tree.len(), 34);
+        let entries: Vec<_> = tree.into_iter().collect();
+        assert_eq!(
+            entries,
+            (0..100)
+                .filter(|k| k % 3 == 0)
+                .map(|k| (k, 2 * k))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let entries = (0..100).map(|i| ((i * 37) % 50, i));
+        let tree: Tree<i32, i32, Size> = entries.collect();
+        assert!(is_avl_tree(&tree));
+        assert_eq!(tree.len(), 50);
+        assert_eq!(tree.summary(), Some(&Size(50)));
+        // The last entry with a key is kept.
+        assert_eq!(tree.get(&0), Some(&50));
+        assert_eq!(tree.get(&37), Some(&51));
+    }
+
+    #[test]
+    fn test_map_values() {
+        let mut tree: Tree<i32, i32, Size> = Tree::default();
+        for i in 0..100 {
+            tree.insert(i, i);
+        }
+        let shape = tree.pretty();
+        let mut order = Vec::new();
+        let tree = tree.map_values(|k, v| {
+            order.push(*k);
+            format!("{}", v * 2)
+        });
+        assert_eq!(order, (0..100).collect::<Vec<_>>());
+        assert_eq!(tree.pretty().lines().count(), shape.lines().count());
+        assert_eq!(tree.get(&21).map(String::as_str), Some("42"));
+        assert_eq!(tree.summary(), Some(&Size(100)));
+        assert_eq!(
+            tree.get_many(&[3, 200, 4]),
+            vec![Some(&"6".to_string()), None, Some(&"8".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_size_summary() {
+        let mut tree: Tree<i32, i32, Size> = Tree::default();
+        for i in 0..50 {
+            tree.insert((i * 7) % 50 * 2, i);
+        }
+        for i in (0..50).step_by(3) {
+            tree.remove(&((i * 7) % 50 * 2));
+        }
+        assert_eq!(tree.summary(), Some(&Size(tree.len())));
+        let keys: Vec<i32> = tree.iter().map(|(k, _)| *k).collect();
+        for (index, key) in keys.iter().enumerate() {
+            assert_eq!(tree.select(index).map(|(k, _)| k), Some(key));
+            assert_eq!(tree.rank(key), index);
+            assert_eq!(tree.rank(&(key + 1)), index + 1);
+        }
+        assert_eq!(tree.select(keys.len()), None);
+    }
+
+    #[test]
+    fn test_modify() {
+        // Summary that sums the values, to check that summaries are
+        // recomputed when values are modified.
+        struct Sum(i32);
+        impl Summary<i32, i32> for Sum {
+            fn summarize(_: &i32, value: &i32, left: Option<&Sum>, right: Option<&Sum>) -> Sum {
+                Sum(value + left.map_or(0, |s| s.0) + right.map_or(0, |s| s.0))
+            }
+        }
+
+        let mut tree: Tree<i32, i32, Sum> = Tree::default();
+        for i in 0..20 {
+            tree.insert(i, i);
+        }
+        assert_eq!(tree.summary().map(|s| s.0), Some(190));
+        assert!(tree.modify(&3, |v| *v += 100));
+        assert!(!tree.modify(&30, |v| *v += 100));
+        assert_eq!(tree.get(&3), Some(&103));
+        assert_eq!(tree.summary().map(|s| s.0), Some(290));
+    }
+
+    #[test]
+    fn test_delete_inner_nodes() {
+        // Deleting from the root removes nodes with two children,
+        // which are replaced by the smallest node of the right subtree.
+        let mut tree = Tree::new();
+        for i in 0..64 {
+            tree.insert(i, i);
+        }
+        for i in 0..64 {
+            let key = tree.root.as_ref().unwrap().key;
+            assert_eq!(tree.delete(key), Ok(()));
+            assert!(is_avl_tree(&tree), "Tree was not AVL:\n{}", tree.pretty());
+            assert_eq!(tree.find(key), None);
+            let remaining = (0..64).filter(|k| tree.find(*k).is_some()).count();
+            assert_eq!(remaining, 63 - i);
+        }
+    }
+
+    #[test]
+    fn test_replace_weighted() {
+        // Summary weighing each entry by its value, to check that
+        // summaries are recomputed when insert replaces a value.
+        struct Total(usize);
+        impl Summary<i32, usize> for Total {
+            fn summarize(
+                _: &i32,
+                value: &usize,
+                left: Option<&Total>,
+                right: Option<&Total>,
+            ) -> Total {
+                Total(value + left.map_or(0, |s| s.0) + right.map_or(0, |s| s.0))
+            }
+        }
+        impl Weight<i32, usize> for Total {
+            fn weight(_: &i32, value: &usize) -> usize {
+                *value
+            }
+
+            fn total(&self) -> usize {
+                self.0
+            }
+        }
+
+        let mut tree: Tree<i32, usize, Total> = Tree::default();
+        for i in 0..10 {
+            tree.insert(i, 1);
+        }
+        assert_eq!(tree.total_weight(), 10);
+        assert_eq!(tree.insert(3, 5), Some(1));
+        assert_eq!(tree.total_weight(), 14);
+        assert_eq!(tree.select(3).map(|(k, _)| *k), Some(3));
+        assert_eq!(tree.select(7).map(|(k, _)| *k), Some(3));
+        assert_eq!(tree.select(8).map(|(k, _)| *k), Some(4));
+    }
+
     #[test]
     fn test_rotate_right() {
         let root = Box::new(Node {
             key: 1,
             value: 1,
             height: 3,
+            summary: (),
             left: Some(Box::new(Node {
                 key: 2,
                 value: 2,
                 height: 2,
+                summary: (),
                 left: Some(Box::new(Node {
                     key: 3,
                     value: 3,
                     height: 1,
+                    summary: (),
                     left: None,
                     right: None,
                 })),
@@ -18,28 +18,38 @@ pub enum Error {
     Eval(tree::Error),
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match *self {
+            Error::Parser(ref error) => write!(f, "{}", error),
+            Error::Eval(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
 
 pub mod parser;
 pub mod tokens;
 pub mod tree;
 
-impl std::convert::From<parser::Error> for Error {
+impl core::convert::From<parser::Error> for Error {
     fn from(error: parser::Error) -> Error {
         Error::Parser(error)
     }
 }
 
-impl std::convert::From<tree::Error> for Error {
+impl core::convert::From<tree::Error> for Error {
     fn from(error: tree::Error) -> Error {
         Error::Eval(error)
     }
 }
 
 pub use self::parser::parse;
+pub use self::tree::Environment;
 
-use std::collections::HashMap;
-
-pub fn eval(expr: &str, map: &HashMap<String, f64>) -> Result<f64> {
-    parse(expr)?.eval(map).map_err(|err| err.into())
+/// Parse and evaluate an expression with the variables given by an
+/// environment, for example a `BTreeMap<String, f64>`.
+pub fn eval<E: Environment + ?Sized>(expr: &str, env: &E) -> Result<f64> {
+    parse(expr)?.eval(env).map_err(|err| err.into())
 }
@@ -14,9 +14,10 @@
 
 //! Predictive expression parse for expressions.
 
-use std::clone::Clone;
 use super::tokens::{Token, Tokenizer};
 use super::tree::ExprTree;
+use alloc::boxed::Box;
+use core::clone::Clone;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
@@ -24,8 +25,8 @@ pub enum Error {
     UnexpectedToken { token: Token, rule: &'static str },
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match *self {
             Error::UnexpectedEndOfInput { ref rule } => {
                 write!(f, "unexpected end of input when parsing {}", rule)
@@ -38,7 +39,7 @@ impl std::fmt::Display for Error {
     }
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 /// Parse expression.
 ///
@@ -46,9 +47,12 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// The grammar is given by the following rules:
 ///
 /// expr ::= term (("+" | "-") term)*
-/// term ::= factor (("*" | "/") factor)*
+/// term ::= power (("*" | "/") power)*
+/// power ::= factor ("^" power)?
 /// factor ::= number | variable | "(" expr ")"
 ///
+/// The power operator is right-associative, so `2^3^2` is `2^(3^2)`.
+///
 /// # Returns
 ///
 /// An expression tree
@@ -57,114 +61,104 @@ pub type Result<T> = std::result::Result<T, Error>;
 ///
 /// ```
 /// # use examples::expr::parse;
-/// # use std::collections::HashMap;
+/// # use std::collections::BTreeMap;
 /// let tree = parse("10 + 10").unwrap();
-/// let map = HashMap::new();
+/// let map: BTreeMap<String, f64> = BTreeMap::new();
 /// assert_eq!(tree.eval(&map), Ok(20.0));
 /// ```
 pub fn parse(text: &str) -> Result<ExprTree> {
     let mut tokens = Tokenizer::new(text);
     let tree = expr_rule(&mut tokens);
-    let result = match tokens.next() {
+    match tokens.next() {
         None => tree,
         Some(tok) => Err(Error::UnexpectedToken {
             token: tok,
             rule: "expr",
         }),
-    };
-    result
+    }
 }
 
 fn expr_rule(tokens: &mut Tokenizer) -> Result<ExprTree> {
     let mut tree = term_rule(tokens)?;
-    loop {
-        match {
-            let tok = tokens.clone().next();
-            tok
-        } {
-            Some(Token::Plus) | Some(Token::Minus) => {
-                let tok = tokens.next().expect("expected '+' or '-'");
-                let rhs = term_rule(tokens)?;
-                match tok {
-                    Token::Plus => {
-                        tree = ExprTree::Add(Box::new(tree), Box::new(rhs));
-                    }
-                    Token::Minus => {
-                        tree = ExprTree::Sub(Box::new(tree), Box::new(rhs));
-                    }
-                    tok => {
-                        return Err(Error::UnexpectedToken {
-                            token: tok,
-                            rule: "expr",
-                        });
-                    }
-                }
+    while let Some(Token::Plus) | Some(Token::Minus) = tokens.clone().next() {
+        let tok = tokens.next().expect("expected '+' or '-'");
+        let rhs = term_rule(tokens)?;
+        match tok {
+            Token::Plus => {
+                tree = ExprTree::Add(Box::new(tree), Box::new(rhs));
+            }
+            Token::Minus => {
+                tree = ExprTree::Sub(Box::new(tree), Box::new(rhs));
+            }
+            tok => {
+                return Err(Error::UnexpectedToken {
+                    token: tok,
+                    rule: "expr",
+                });
             }
-            _ => break,
         }
     }
     Ok(tree)
 }
 
 fn term_rule(tokens: &mut Tokenizer) -> Result<ExprTree> {
-    let mut tree = factor_rule(tokens)?;
-    loop {
-        match {
-            let tok = tokens.clone().next();
-            tok
-        } {
-            Some(Token::Star) | Some(Token::Slash) => {
-                let tok = tokens.next().expect("expected '*' or '/'");
-                let rhs = factor_rule(tokens)?;
-                match tok {
-                    Token::Star => {
-                        tree = ExprTree::Mul(Box::new(tree), Box::new(rhs));
-                    }
-                    Token::Slash => {
-                        tree = ExprTree::Div(Box::new(tree), Box::new(rhs));
-                    }
-                    tok => {
-                        return Err(Error::UnexpectedToken {
-                            token: tok,
-                            rule: "term",
-                        });
-                    }
-                }
+    let mut tree = power_rule(tokens)?;
+    while let Some(Token::Star) | Some(Token::Slash) = tokens.clone().next() {
+        let tok = tokens.next().expect("expected '*' or '/'");
+        let rhs = power_rule(tokens)?;
+        match tok {
+            Token::Star => {
+                tree = ExprTree::Mul(Box::new(tree), Box::new(rhs));
+            }
+            Token::Slash => {
+                tree = ExprTree::Div(Box::new(tree), Box::new(rhs));
+            }
+            tok => {
+                return Err(Error::UnexpectedToken {
+                    token: tok,
+                    rule: "term",
+                });
             }
-            _ => break,
         }
     }
     Ok(tree)
 }
 
+fn power_rule(tokens: &mut Tokenizer) -> Result<ExprTree> {
+    let base = factor_rule(tokens)?;
+    if let Some(Token::Power) = tokens.clone().next() {
+        tokens.next();
+        let exponent = power_rule(tokens)?;
+        return Ok(ExprTree::Pow(Box::new(base), Box::new(exponent)));
+    }
+    Ok(base)
+}
+
 fn factor_rule(tokens: &mut Tokenizer) -> Result<ExprTree> {
-    let result = {
-        let tok = tokens
-            .next()
-            .ok_or(Error::UnexpectedEndOfInput { rule: "factor" })?;
-        match tok {
-            Token::Float(number) => Ok(ExprTree::Float(number)),
-            Token::Symbol(name) => Ok(ExprTree::Var(name)),
-            Token::Open => {
-                let expr = expr_rule(tokens)?;
-                match tokens
-                    .next()
-                    .ok_or(Error::UnexpectedEndOfInput { rule: "factor" })?
-                {
-                    Token::Close => Ok(expr),
-                    tok => Err(Error::UnexpectedToken {
-                        token: tok,
-                        rule: "factor",
-                    }),
-                }
+    let tok = tokens
+        .next()
+        .ok_or(Error::UnexpectedEndOfInput { rule: "factor" })?;
+    match tok {
+        Token::Float(number) => Ok(ExprTree::Float(number)),
+        Token::Symbol(name) => Ok(ExprTree::Var(name)),
+        Token::Open => {
+            let expr = expr_rule(tokens)?;
+            match tokens
+                .next()
+                .ok_or(Error::UnexpectedEndOfInput { rule: "factor" })?
+            {
+                Token::Close => Ok(expr),
+                tok => Err(Error::UnexpectedToken {
+                    token: tok,
+                    rule: "factor",
+                }),
             }
-            tok => Err(Error::UnexpectedToken {
-                token: tok,
-                rule: "factor",
-            }),
         }
-    };
-    result
+        tok => Err(Error::UnexpectedToken {
+            token: tok,
+            rule: "factor",
+        }),
+    }
 }
 
 #[cfg(test)]
@@ -208,6 +202,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn power_parse() {
+        check("2^3", Pow(Box::new(Float(2.0)), Box::new(Float(3.0))));
+        check(
+            "2^3^2",
+            Pow(
+                Box::new(Float(2.0)),
+                Box::new(Pow(Box::new(Float(3.0)), Box::new(Float(2.0)))),
+            ),
+        );
+        check(
+            "2*x^2",
+            Mul(
+                Box::new(Float(2.0)),
+                Box::new(Pow(Box::new(Var("x".to_string())), Box::new(Float(2.0)))),
+            ),
+        );
+        check(
+            "(1+2)^x/4",
+            Div(
+                Box::new(Pow(
+                    Box::new(Add(Box::new(Float(1.0)), Box::new(Float(2.0)))),
+                    Box::new(Var("x".to_string())),
+                )),
+                Box::new(Float(4.0)),
+            ),
+        );
+        assert_eq!(parse("2^"), Err(UnexpectedEndOfInput { rule: "factor" }));
+        assert_eq!(
+            parse("2^*"),
+            Err(UnexpectedToken {
+                token: Token::Star,
+                rule: "factor"
+            })
+        );
+    }
+
     #[test]
     fn bad_parse() {
         assert_eq!(
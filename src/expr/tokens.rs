@@ -15,7 +15,8 @@
 //! Tokenizer to read character from an iterator over some text and
 //! produce tokens for the shunting-yard algorithm.
 
-use std::str::Chars;
+use alloc::string::{String, ToString};
+use core::str::Chars;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
@@ -31,8 +32,8 @@ pub enum Token {
     Close,
 }
 
-impl std::fmt::Display for Token {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for Token {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match *self {
             Token::Plus => write!(f, "+"),
             Token::Minus => write!(f, "-"),
@@ -86,7 +87,7 @@ impl<'a> Tokenizer<'a> {
     where
         P: Copy + FnMut(char) -> bool,
     {
-        while self.chars.clone().next().map_or(false, pred) {
+        while self.chars.clone().next().is_some_and(pred) {
             self.chars.next();
         }
     }
@@ -98,13 +99,13 @@ impl<'a> Iterator for Tokenizer<'a> {
     fn next(&mut self) -> Option<Token> {
         self.skip_while(|ch| ch.is_whitespace());
         match self.chars.clone().next() {
-            Some(ch) if ch.is_digit(10) => self
-                .take_while(|ch| ch.is_digit(10) || ch == '.')
+            Some(ch) if ch.is_ascii_digit() => self
+                .take_while(|ch| ch.is_ascii_digit() || ch == '.')
                 .parse::<f64>()
-                .map(|num| Token::Float(num))
+                .map(Token::Float)
                 .ok(),
             Some(ch) if ch.is_alphabetic() => {
-                let name = self.take_while(|c| c.is_alphabetic() || c == '_' || c.is_digit(10));
+                let name = self.take_while(|c| c.is_alphabetic() || c == '_' || c.is_ascii_digit());
                 Some(Token::Symbol(name.to_string()))
             }
             Some('+') => {
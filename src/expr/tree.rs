@@ -12,14 +12,56 @@
 // implied.  See the License for the specific language governing
 // permissions and limitations under the License.
 
+use crate::avl::tree::{Summary, Tree};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
 use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
     NoValue(String),
+    /// `^` was used in a build with neither `std` nor `libm`.
+    NoPow,
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match *self {
+            Error::NoValue(ref name) => write!(f, "no value for variable '{}'", name),
+            Error::NoPow => write!(f, "'^' needs the std or libm feature"),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Values of the variables in an expression.
+pub trait Environment {
+    fn value(&self, name: &str) -> Option<f64>;
+}
+
+#[cfg(feature = "std")]
+impl<H: BuildHasher> Environment for HashMap<String, f64, H> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl Environment for BTreeMap<String, f64> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl<S: Summary<String, f64>> Environment for Tree<String, f64, S> {
+    fn value(&self, name: &str) -> Option<f64> {
+        self.get(&name.to_string()).copied()
+    }
+}
 
 #[derive(Debug, PartialEq)]
 pub enum ExprTree {
@@ -29,20 +71,85 @@ pub enum ExprTree {
     Sub(Box<ExprTree>, Box<ExprTree>),
     Mul(Box<ExprTree>, Box<ExprTree>),
     Div(Box<ExprTree>, Box<ExprTree>),
+    Pow(Box<ExprTree>, Box<ExprTree>),
+}
+
+/// Raise a number to a power.
+#[cfg(feature = "std")]
+fn pow(base: f64, exponent: f64) -> Result<f64> {
+    Ok(base.powf(exponent))
+}
+
+/// Raise a number to a power.
+///
+/// There is no `powf` without `std`, so the one from `libm` is used.
+#[cfg(all(not(feature = "std"), feature = "libm"))]
+fn pow(base: f64, exponent: f64) -> Result<f64> {
+    Ok(libm::pow(base, exponent))
+}
+
+/// Raising to a power is not available without `std` or `libm`.
+#[cfg(not(any(feature = "std", feature = "libm")))]
+fn pow(_base: f64, _exponent: f64) -> Result<f64> {
+    Err(Error::NoPow)
 }
 
 impl ExprTree {
-    pub fn eval(self, map: &HashMap<String, f64>) -> Result<f64> {
+    pub fn eval<E: Environment + ?Sized>(&self, env: &E) -> Result<f64> {
         match self {
-            ExprTree::Float(num) => Ok(num),
-            ExprTree::Var(name) => map
-                .get(&name)
-                .ok_or(Error::NoValue(name.clone()))
-                .map(Clone::clone),
-            ExprTree::Add(lhs, rhs) => Ok(lhs.eval(map)? + rhs.eval(map)?),
-            ExprTree::Sub(lhs, rhs) => Ok(lhs.eval(map)? - rhs.eval(map)?),
-            ExprTree::Mul(lhs, rhs) => Ok(lhs.eval(map)? * rhs.eval(map)?),
-            ExprTree::Div(lhs, rhs) => Ok(lhs.eval(map)? / rhs.eval(map)?),
+            ExprTree::Float(num) => Ok(*num),
+            ExprTree::Var(name) => env.value(name).ok_or_else(|| Error::NoValue(name.clone())),
+            ExprTree::Add(lhs, rhs) => Ok(lhs.eval(env)? + rhs.eval(env)?),
+            ExprTree::Sub(lhs, rhs) => Ok(lhs.eval(env)? - rhs.eval(env)?),
+            ExprTree::Mul(lhs, rhs) => Ok(lhs.eval(env)? * rhs.eval(env)?),
+            ExprTree::Div(lhs, rhs) => Ok(lhs.eval(env)? / rhs.eval(env)?),
+            ExprTree::Pow(lhs, rhs) => pow(lhs.eval(env)?, rhs.eval(env)?),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::parse;
+
+    #[test]
+    fn environments() {
+        let mut map = BTreeMap::new();
+        map.insert("x".to_string(), 2.0);
+        assert_eq!(parse("x * 10").unwrap().eval(&map), Ok(20.0));
+
+        let mut tree = Tree::new();
+        tree.insert("x".to_string(), 3.0);
+        assert_eq!(parse("x * 10").unwrap().eval(&tree), Ok(30.0));
+        assert_eq!(
+            parse("x + y").unwrap().eval(&tree),
+            Err(Error::NoValue("y".to_string()))
+        );
+    }
+
+    #[test]
+    #[cfg(any(feature = "std", feature = "libm"))]
+    fn power() {
+        let mut map = BTreeMap::new();
+        map.insert("x".to_string(), 3.0);
+        let eval = |text: &str| parse(text).unwrap().eval(&map);
+        assert_eq!(eval("2^3"), Ok(8.0));
+        assert_eq!(eval("2^3^2"), Ok(512.0));
+        assert_eq!(eval("(2^3)^2"), Ok(64.0));
+        assert_eq!(eval("2*x^2"), Ok(18.0));
+        assert_eq!(eval("2^(0-2)"), Ok(0.25));
+        assert_eq!(eval("x^0"), Ok(1.0));
+        assert_eq!(eval("16^0.5"), Ok(4.0));
+        assert_eq!(eval("4^(0-0.5)"), Ok(0.5));
+        assert_eq!(eval("(0-8)^(1/3)").map(f64::is_nan), Ok(true));
+        assert_eq!(eval("2^2000"), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "std", feature = "libm")))]
+    fn power_unavailable() {
+        let map = BTreeMap::new();
+        assert_eq!(parse("2^3").unwrap().eval(&map), Err(Error::NoPow));
+    }
+}
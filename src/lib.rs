@@ -12,5 +12,18 @@
 // implied.  See the License for the specific language governing
 // permissions and limitations under the License.
 
+//! Examples of data structures and algorithms.
+//!
+//! With the default `std` feature disabled, the crate is `no_std` and
+//! only needs `alloc`. The AVL tree in `avl::tree` and the expression
+//! parser and evaluator in `expr` are available, while the other
+//! modules need `std`. Evaluating `^` then needs the `libm` feature.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
 pub mod avl;
 pub mod expr;
+#[cfg(feature = "std")]
+pub mod map;
@@ -0,0 +1,302 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Ordered maps with different balancing schemes.
+//!
+//! The `OrderedMap` trait captures the operations shared by the AVL
+//! tree, the red-black tree and the treap in this module, and the
+//! `BTreeMap` from the standard library, so that the same workload
+//! can be run on each of them.
+
+pub mod rbtree;
+pub mod treap;
+
+pub use self::rbtree::RbTree;
+pub use self::treap::Treap;
+
+use crate::avl;
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::ptr;
+
+/// Boxed iterator over the entries of an ordered map.
+pub type BoxedIter<'a, K, V> = Box<dyn Iterator<Item = (&'a K, &'a V)> + 'a>;
+
+pub trait OrderedMap<K, V> {
+    /// Insert a value under a key, returning the old value if the key
+    /// was already present.
+    fn insert(&mut self, key: K, value: V) -> Option<V>;
+
+    /// Remove a key, returning the value if the key was present.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Get a reference to the value stored under a key.
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Iterate over the entries in a range of keys, in key order.
+    ///
+    /// Ranges where the start is after the end produce an empty
+    /// iterator.
+    fn range<R>(&self, range: R) -> BoxedIter<'_, K, V>
+    where
+        R: RangeBounds<K>;
+
+    /// Iterate over all entries in key order.
+    fn iter(&self) -> BoxedIter<'_, K, V>;
+
+    /// Number of entries in the map.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> OrderedMap<K, V> for avl::Tree<K, V, S>
+where
+    K: PartialOrd,
+    S: avl::Summary<K, V>,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        avl::Tree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        avl::Tree::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        avl::Tree::get(self, key)
+    }
+
+    fn range<R>(&self, range: R) -> BoxedIter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        Box::new(avl::Tree::range(self, range))
+    }
+
+    fn iter(&self) -> BoxedIter<'_, K, V> {
+        Box::new(avl::Tree::iter(self))
+    }
+
+    fn len(&self) -> usize {
+        avl::Tree::len(self)
+    }
+}
+
+impl<K, V> OrderedMap<K, V> for BTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        BTreeMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        BTreeMap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        BTreeMap::get(self, key)
+    }
+
+    fn range<R>(&self, range: R) -> BoxedIter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        // BTreeMap::range panics on inverted ranges, so those are
+        // handled here instead.
+        let inverted = match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            (Bound::Included(start), Bound::Included(end))
+            | (Bound::Included(start), Bound::Excluded(end))
+            | (Bound::Excluded(start), Bound::Included(end)) => start > end,
+            _ => false,
+        };
+        if inverted {
+            Box::new(std::iter::empty())
+        } else {
+            Box::new(BTreeMap::range(self, range))
+        }
+    }
+
+    fn iter(&self) -> BoxedIter<'_, K, V> {
+        Box::new(BTreeMap::iter(self))
+    }
+
+    fn len(&self) -> usize {
+        BTreeMap::len(self)
+    }
+}
+
+/// Node of a binary search tree.
+///
+/// This is used to share the iterator between the red-black tree and
+/// the treap, which only differ in how they balance the tree.
+trait SearchNode {
+    type Key: PartialOrd;
+    type Value;
+
+    fn key(&self) -> &Self::Key;
+    fn value(&self) -> &Self::Value;
+    fn left(&self) -> Option<&Self>;
+    fn right(&self) -> Option<&Self>;
+}
+
+/// In-order iterator over a range of a binary search tree.
+///
+/// The stack holds the nodes still to be visited on the path to the
+/// next node, which is on top of the stack. The iteration stops after
+/// the last node in the range has been produced.
+struct SearchIter<'a, N> {
+    stack: Vec<&'a N>,
+    last: Option<&'a N>,
+}
+
+impl<'a, N> SearchIter<'a, N>
+where
+    N: SearchNode,
+{
+    fn new<R>(root: Option<&'a N>, range: R) -> SearchIter<'a, N>
+    where
+        R: RangeBounds<N::Key>,
+    {
+        let mut stack = Vec::new();
+        let mut current = root;
+        while let Some(node) = current {
+            let after_start = match range.start_bound() {
+                Bound::Included(key) => node.key() >= key,
+                Bound::Excluded(key) => node.key() > key,
+                Bound::Unbounded => true,
+            };
+            if after_start {
+                stack.push(node);
+                current = node.left();
+            } else {
+                current = node.right();
+            }
+        }
+
+        let mut last = None;
+        let mut current = root;
+        while let Some(node) = current {
+            let before_end = match range.end_bound() {
+                Bound::Included(key) => node.key() <= key,
+                Bound::Excluded(key) => node.key() < key,
+                Bound::Unbounded => true,
+            };
+            if before_end {
+                last = Some(node);
+                current = node.right();
+            } else {
+                current = node.left();
+            }
+        }
+
+        match (stack.last(), last) {
+            (Some(first), Some(last)) if first.key() <= last.key() => {}
+            _ => stack.clear(),
+        }
+        SearchIter { stack, last }
+    }
+}
+
+impl<'a, N> Iterator for SearchIter<'a, N>
+where
+    N: SearchNode,
+{
+    type Item = (&'a N::Key, &'a N::Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if self.last.is_some_and(|last| ptr::eq(node, last)) {
+            self.stack.clear();
+        } else {
+            let mut current = node.right();
+            while let Some(next) = current {
+                self.stack.push(next);
+                current = next.left();
+            }
+        }
+        Some((node.key(), node.value()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run the same sequence of operations on a map and check the
+    /// result against the expected one.
+    fn check_map<M>(mut map: M)
+    where
+        M: OrderedMap<i32, i32>,
+    {
+        assert!(map.is_empty());
+        for i in 0..100 {
+            let key = (i * 37) % 100;
+            assert_eq!(map.insert(key, i), None);
+        }
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.insert(37, -1), Some(1));
+        assert_eq!(map.get(&37), Some(&-1));
+        assert_eq!(map.get(&100), None);
+
+        let keys: Vec<i32> = map.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (0..100).collect::<Vec<_>>());
+        let keys: Vec<i32> = map.range(10..15).map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![10, 11, 12, 13, 14]);
+        let keys: Vec<i32> = map.range(95..).map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![95, 96, 97, 98, 99]);
+        assert_eq!(
+            map.range((Bound::Included(15), Bound::Excluded(10)))
+                .count(),
+            0
+        );
+        assert_eq!(
+            map.range((Bound::Excluded(10), Bound::Excluded(10)))
+                .count(),
+            0
+        );
+
+        for key in (0..100).filter(|k| k % 2 == 0) {
+            assert!(map.remove(&key).is_some());
+        }
+        assert_eq!(map.remove(&0), None);
+        assert_eq!(map.len(), 50);
+        let keys: Vec<i32> = map.range(..=9).map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![1, 3, 5, 7, 9]);
+    }
+
+    #[test]
+    fn test_avl() {
+        check_map(avl::Tree::new());
+    }
+
+    #[test]
+    fn test_btree() {
+        check_map(BTreeMap::new());
+    }
+
+    #[test]
+    fn test_rbtree() {
+        check_map(RbTree::new());
+    }
+
+    #[test]
+    fn test_treap() {
+        check_map(Treap::new());
+    }
+}
@@ -0,0 +1,398 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Left-leaning red-black tree.
+//!
+//! This is the variant of red-black trees described by Sedgewick,
+//! where red links always lean left. This means that there is a
+//! one-to-one correspondence with 2-3 trees, which keeps the number of
+//! cases to consider when rebalancing small.
+
+use super::{BoxedIter, OrderedMap, SearchIter, SearchNode};
+use std::mem;
+use std::ops::RangeBounds;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    red: bool,
+    left: Option<Inner<K, V>>,
+    right: Option<Inner<K, V>>,
+}
+
+type Inner<K, V> = Box<Node<K, V>>;
+
+fn is_red<K, V>(node: &Option<Inner<K, V>>) -> bool {
+    node.as_ref().is_some_and(|n| n.red)
+}
+
+/// Check if the left child of a node is red, which is only true if
+/// the node exists.
+fn is_left_red<K, V>(node: &Option<Inner<K, V>>) -> bool {
+    node.as_ref().is_some_and(|n| is_red(&n.left))
+}
+
+fn rotate_left<K, V>(mut root: Inner<K, V>) -> Inner<K, V> {
+    let mut new_root = root.right.take().expect("no right subtree");
+    root.right = new_root.left.take();
+    new_root.red = root.red;
+    root.red = true;
+    new_root.left = Some(root);
+    new_root
+}
+
+fn rotate_right<K, V>(mut root: Inner<K, V>) -> Inner<K, V> {
+    let mut new_root = root.left.take().expect("no left subtree");
+    root.left = new_root.right.take();
+    new_root.red = root.red;
+    root.red = true;
+    new_root.right = Some(root);
+    new_root
+}
+
+/// Flip the colors of a node and its two children, which corresponds
+/// to splitting or merging a 4-node in the 2-3 tree.
+fn flip_colors<K, V>(node: &mut Node<K, V>) {
+    node.red = !node.red;
+    if let Some(left) = node.left.as_mut() {
+        left.red = !left.red;
+    }
+    if let Some(right) = node.right.as_mut() {
+        right.red = !right.red;
+    }
+}
+
+/// Restore the left-leaning invariants on the way up from an insert
+/// or a delete.
+fn fix_up<K, V>(mut root: Inner<K, V>) -> Inner<K, V> {
+    if is_red(&root.right) && !is_red(&root.left) {
+        root = rotate_left(root);
+    }
+    if is_red(&root.left) && is_left_red(&root.left) {
+        root = rotate_right(root);
+    }
+    if is_red(&root.left) && is_red(&root.right) {
+        flip_colors(&mut root);
+    }
+    root
+}
+
+/// Make sure that the left child or one of its children is red, so
+/// that a node can be removed from the left subtree.
+fn move_red_left<K, V>(mut root: Inner<K, V>) -> Inner<K, V> {
+    flip_colors(&mut root);
+    if is_left_red(&root.right) {
+        root.right = Some(rotate_right(root.right.take().unwrap()));
+        root = rotate_left(root);
+        flip_colors(&mut root);
+    }
+    root
+}
+
+/// Make sure that the right child or one of its children is red, so
+/// that a node can be removed from the right subtree.
+fn move_red_right<K, V>(mut root: Inner<K, V>) -> Inner<K, V> {
+    flip_colors(&mut root);
+    if is_left_red(&root.left) {
+        root = rotate_right(root);
+        flip_colors(&mut root);
+    }
+    root
+}
+
+/// Take out the smallest node of a subtree, returning the new subtree
+/// and the smallest node.
+fn take_smallest<K, V>(mut root: Inner<K, V>) -> (Option<Inner<K, V>>, Inner<K, V>) {
+    if root.left.is_none() {
+        // Since red links lean left, a node without a left child
+        // cannot have a right child either.
+        return (None, root);
+    }
+    if !is_red(&root.left) && !is_left_red(&root.left) {
+        root = move_red_left(root);
+    }
+    let (left, node) = take_smallest(root.left.take().unwrap());
+    root.left = left;
+    (Some(fix_up(root)), node)
+}
+
+fn insert_node<K, V>(root: Option<Inner<K, V>>, key: K, value: V) -> (Inner<K, V>, Option<V>)
+where
+    K: PartialOrd,
+{
+    match root {
+        None => (Box::new(Node::new(key, value)), None),
+        Some(mut node) => {
+            let old_value = if key < node.key {
+                let (left, old_value) = insert_node(node.left.take(), key, value);
+                node.left = Some(left);
+                old_value
+            } else if key > node.key {
+                let (right, old_value) = insert_node(node.right.take(), key, value);
+                node.right = Some(right);
+                old_value
+            } else {
+                Some(mem::replace(&mut node.value, value))
+            };
+            (fix_up(node), old_value)
+        }
+    }
+}
+
+/// Remove a key from a subtree, where the key is known to be present.
+fn remove_node<K, V>(mut root: Inner<K, V>, key: &K) -> (Option<Inner<K, V>>, V)
+where
+    K: PartialOrd,
+{
+    if *key < root.key {
+        if !is_red(&root.left) && !is_left_red(&root.left) {
+            root = move_red_left(root);
+        }
+        let (left, value) = remove_node(root.left.take().unwrap(), key);
+        root.left = left;
+        (Some(fix_up(root)), value)
+    } else {
+        if is_red(&root.left) {
+            root = rotate_right(root);
+        }
+        if *key == root.key && root.right.is_none() {
+            return (None, root.value);
+        }
+        if !is_red(&root.right) && !is_left_red(&root.right) {
+            root = move_red_right(root);
+        }
+        if *key == root.key {
+            let (right, smallest) = take_smallest(root.right.take().unwrap());
+            let Node {
+                key: next_key,
+                value: next_value,
+                ..
+            } = *smallest;
+            root.key = next_key;
+            root.right = right;
+            let value = mem::replace(&mut root.value, next_value);
+            (Some(fix_up(root)), value)
+        } else {
+            let (right, value) = remove_node(root.right.take().unwrap(), key);
+            root.right = right;
+            (Some(fix_up(root)), value)
+        }
+    }
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: K, value: V) -> Node<K, V> {
+        Node {
+            key,
+            value,
+            red: true,
+            left: None,
+            right: None,
+        }
+    }
+}
+
+impl<K, V> SearchNode for Node<K, V>
+where
+    K: PartialOrd,
+{
+    type Key = K;
+    type Value = V;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn value(&self) -> &V {
+        &self.value
+    }
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+}
+
+pub struct RbTree<K, V> {
+    root: Option<Inner<K, V>>,
+    len: usize,
+}
+
+impl<K, V> Default for RbTree<K, V> {
+    fn default() -> RbTree<K, V> {
+        RbTree { root: None, len: 0 }
+    }
+}
+
+impl<K, V> RbTree<K, V>
+where
+    K: PartialOrd,
+{
+    pub fn new() -> RbTree<K, V> {
+        RbTree::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Insert value into tree under the given key, returning the old
+    /// value if the key was already present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (mut root, old_value) = insert_node(self.root.take(), key, value);
+        root.red = false;
+        self.root = Some(root);
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        old_value
+    }
+
+    /// Remove a key from the tree, returning the value if the key was
+    /// present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        // The rebalancing on the way down assumes that the key is
+        // present, so check that first.
+        self.get(key)?;
+        let mut root = self.root.take().unwrap();
+        if !is_red(&root.left) && !is_red(&root.right) {
+            root.red = true;
+        }
+        let (root, value) = remove_node(root, key);
+        self.root = root.map(|mut root| {
+            root.red = false;
+            root
+        });
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut current = &self.root;
+        while let Some(node) = current {
+            if *key < node.key {
+                current = &node.left;
+            } else if *key > node.key {
+                current = &node.right;
+            } else {
+                return Some(&node.value);
+            }
+        }
+        None
+    }
+
+    /// Iterate over the entries with keys in a range, in key order.
+    pub fn range<R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        Iter(SearchIter::new(self.root.as_deref(), range))
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.range(..)
+    }
+}
+
+/// Iterator over the entries of a red-black tree in key order.
+pub struct Iter<'a, K, V>(SearchIter<'a, Node<K, V>>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: PartialOrd,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<K, V> OrderedMap<K, V> for RbTree<K, V>
+where
+    K: PartialOrd,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        RbTree::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        RbTree::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        RbTree::get(self, key)
+    }
+
+    fn range<R>(&self, range: R) -> BoxedIter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        Box::new(RbTree::range(self, range))
+    }
+
+    fn iter(&self) -> BoxedIter<'_, K, V> {
+        Box::new(RbTree::iter(self))
+    }
+
+    fn len(&self) -> usize {
+        RbTree::len(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the red-black invariants of a subtree, returning the
+    /// number of black links on each path to a leaf.
+    fn black_height<K, V>(node: &Option<Inner<K, V>>) -> Option<usize> {
+        match node {
+            None => Some(0),
+            Some(node) => {
+                if is_red(&node.right) || (node.red && is_red(&node.left)) {
+                    return None;
+                }
+                let left = black_height(&node.left)?;
+                let right = black_height(&node.right)?;
+                if left != right {
+                    return None;
+                }
+                Some(left + if node.red { 0 } else { 1 })
+            }
+        }
+    }
+
+    #[test]
+    fn test_invariants() {
+        let mut tree = RbTree::new();
+        for i in 0..200 {
+            tree.insert((i * 71) % 200, i);
+            assert!(black_height(&tree.root).is_some());
+        }
+        for i in 0..200 {
+            assert!(tree.remove(&((i * 13) % 200)).is_some());
+            assert!(black_height(&tree.root).is_some());
+        }
+        assert!(tree.is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you
+// may not use this file except in compliance with the License.  You
+// may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or
+// implied.  See the License for the specific language governing
+// permissions and limitations under the License.
+
+//! Treap, a randomized binary search tree.
+//!
+//! Each node is given a random priority and the tree is kept ordered
+//! by key as a search tree and by priority as a heap. The expected
+//! height is then logarithmic regardless of the insertion order.
+
+use super::{BoxedIter, OrderedMap, SearchIter, SearchNode};
+use std::mem;
+use std::ops::RangeBounds;
+
+struct Node<K, V> {
+    key: K,
+    value: V,
+    priority: u64,
+    left: Option<Inner<K, V>>,
+    right: Option<Inner<K, V>>,
+}
+
+type Inner<K, V> = Box<Node<K, V>>;
+
+/// Subtree that can be empty.
+type Link<K, V> = Option<Inner<K, V>>;
+
+/// Split a subtree into the nodes with keys smaller than the key and
+/// the nodes with keys larger than the key. The key must not be
+/// present in the subtree.
+fn split<K, V>(root: Link<K, V>, key: &K) -> (Link<K, V>, Link<K, V>)
+where
+    K: PartialOrd,
+{
+    match root {
+        None => (None, None),
+        Some(mut node) => {
+            if node.key < *key {
+                let (left, right) = split(node.right.take(), key);
+                node.right = left;
+                (Some(node), right)
+            } else {
+                let (left, right) = split(node.left.take(), key);
+                node.left = right;
+                (left, Some(node))
+            }
+        }
+    }
+}
+
+/// Merge two subtrees where all keys in the left subtree are smaller
+/// than the keys in the right subtree.
+fn merge<K, V>(left: Link<K, V>, right: Link<K, V>) -> Link<K, V> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => {
+            if left.priority > right.priority {
+                left.right = merge(left.right.take(), Some(right));
+                Some(left)
+            } else {
+                right.left = merge(Some(left), right.left.take());
+                Some(right)
+            }
+        }
+    }
+}
+
+fn remove_node<K, V>(root: Link<K, V>, key: &K) -> (Link<K, V>, Option<V>)
+where
+    K: PartialOrd,
+{
+    match root {
+        None => (None, None),
+        Some(mut node) => {
+            if *key < node.key {
+                let (left, value) = remove_node(node.left.take(), key);
+                node.left = left;
+                (Some(node), value)
+            } else if *key > node.key {
+                let (right, value) = remove_node(node.right.take(), key);
+                node.right = right;
+                (Some(node), value)
+            } else {
+                let Node {
+                    value, left, right, ..
+                } = *node;
+                (merge(left, right), Some(value))
+            }
+        }
+    }
+}
+
+impl<K, V> SearchNode for Node<K, V>
+where
+    K: PartialOrd,
+{
+    type Key = K;
+    type Value = V;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn value(&self) -> &V {
+        &self.value
+    }
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+}
+
+pub struct Treap<K, V> {
+    root: Link<K, V>,
+    len: usize,
+    seed: u64,
+}
+
+impl<K, V> Default for Treap<K, V> {
+    fn default() -> Treap<K, V> {
+        Treap::with_seed(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+impl<K, V> Treap<K, V> {
+    /// Create a treap where priorities are generated from the given
+    /// seed, which makes the shape of the tree reproducible.
+    pub fn with_seed(seed: u64) -> Treap<K, V> {
+        Treap {
+            root: None,
+            len: 0,
+            seed: seed.max(1),
+        }
+    }
+
+    /// Generate the next priority using a xorshift generator.
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl<K, V> Treap<K, V>
+where
+    K: PartialOrd,
+{
+    pub fn new() -> Treap<K, V> {
+        Treap::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Insert value into the treap under the given key, returning the
+    /// old value if the key was already present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(old_value) = self.get_mut(&key) {
+            return Some(mem::replace(old_value, value));
+        }
+        let node = Box::new(Node {
+            key,
+            value,
+            priority: self.next_priority(),
+            left: None,
+            right: None,
+        });
+        let (left, right) = split(self.root.take(), &node.key);
+        self.root = merge(merge(left, Some(node)), right);
+        self.len += 1;
+        None
+    }
+
+    /// Remove a key from the treap, returning the value if the key
+    /// was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let (root, value) = remove_node(self.root.take(), key);
+        self.root = root;
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut current = &self.root;
+        while let Some(node) = current {
+            if *key < node.key {
+                current = &node.left;
+            } else if *key > node.key {
+                current = &node.right;
+            } else {
+                return Some(&node.value);
+            }
+        }
+        None
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut current = &mut self.root;
+        while let Some(node) = current {
+            if *key < node.key {
+                current = &mut node.left;
+            } else if *key > node.key {
+                current = &mut node.right;
+            } else {
+                return Some(&mut node.value);
+            }
+        }
+        None
+    }
+
+    /// Iterate over the entries with keys in a range, in key order.
+    pub fn range<R>(&self, range: R) -> Iter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        Iter(SearchIter::new(self.root.as_deref(), range))
+    }
+
+    /// Iterate over all entries in key order.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.range(..)
+    }
+}
+
+/// Iterator over the entries of a treap in key order.
+pub struct Iter<'a, K, V>(SearchIter<'a, Node<K, V>>);
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+where
+    K: PartialOrd,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+impl<K, V> OrderedMap<K, V> for Treap<K, V>
+where
+    K: PartialOrd,
+{
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        Treap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        Treap::remove(self, key)
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        Treap::get(self, key)
+    }
+
+    fn range<R>(&self, range: R) -> BoxedIter<'_, K, V>
+    where
+        R: RangeBounds<K>,
+    {
+        Box::new(Treap::range(self, range))
+    }
+
+    fn iter(&self) -> BoxedIter<'_, K, V> {
+        Box::new(Treap::iter(self))
+    }
+
+    fn len(&self) -> usize {
+        Treap::len(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that a subtree is ordered as a heap by priority.
+    fn is_heap<K, V>(node: &Link<K, V>) -> bool {
+        node.as_ref().is_none_or(|node| {
+            let children = [&node.left, &node.right];
+            children.iter().all(|child| {
+                child.as_ref().is_none_or(|c| c.priority <= node.priority) && is_heap(child)
+            })
+        })
+    }
+
+    #[test]
+    fn test_heap_order() {
+        let mut treap = Treap::new();
+        for i in 0..200 {
+            treap.insert(i, i);
+            assert!(is_heap(&treap.root));
+        }
+        for i in (0..200).step_by(3) {
+            assert_eq!(treap.remove(&i), Some(i));
+            assert!(is_heap(&treap.root));
+        }
+        let keys: Vec<_> = treap.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (0..200).filter(|i| i % 3 != 0).collect::<Vec<_>>());
+    }
+}
@@ -35,6 +35,9 @@ fn good_eval() {
     assert_eq!(eval("10 + 2 * 3", &map), Ok(16.0));
     assert_eq!(eval("(10 + 2) * 3", &map), Ok(36.0));
     assert_eq!(eval("(10-x)*3", &map), Ok(-6.0));
+    assert_eq!(eval("2^3^2", &map), Ok(512.0));
+    assert_eq!(eval("x^2 / 4", &map), Ok(36.0));
+    assert_eq!(eval("4^0.5 + 1", &map), Ok(3.0));
 }
 
 #[test]